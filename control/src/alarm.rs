@@ -0,0 +1,279 @@
+//! # Thermostat_EEM alarm limits
+//!
+//! Limit checks with hysteresis, a minimum violation duration before the alarm asserts and
+//! optional latching. An asserted limit can act on the outputs.
+
+use miniconf::{Leaf, Tree};
+
+/// Alarm severity.
+#[derive(
+    Copy,
+    Clone,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Severity {
+    /// Attention required.
+    #[default]
+    Warning,
+    /// Immediate action required.
+    Critical,
+}
+
+/// Alarm action on the outputs.
+///
+/// The variants are ordered by precedence. If several asserted limits act on an output, the
+/// action with the highest precedence is applied.
+#[derive(
+    Copy,
+    Clone,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Action {
+    /// No action.
+    #[default]
+    None,
+    /// Flash the output LEDs.
+    Flash,
+    /// Move the outputs to the fallback `setpoint`.
+    Setpoint,
+    /// Set the output state to `Hold`.
+    Hold,
+    /// Shut the outputs down.
+    /// The output `state` is kept and latched output faults are not acknowledged. Without
+    /// `revert` the outputs stay shut down until the alarm is acknowledged.
+    Off,
+}
+
+/// Alarm action on the outputs while a limit is asserted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Tree)]
+pub struct OutputAction {
+    /// Action to take.
+    ///
+    /// # Value
+    /// See [Action]
+    pub mode: Leaf<Action>,
+
+    /// Outputs to act on.
+    ///
+    /// # Value
+    /// `[bool; 4]`
+    pub outputs: Leaf<[bool; 4]>,
+
+    /// Fallback setpoint for the `Setpoint` action.
+    pub setpoint: Leaf<f32>,
+
+    /// Restore the previous output state and setpoint once the alarm clears.
+    /// They are only restored if they have not been changed in the meantime.
+    pub revert: Leaf<bool>,
+}
+
+/// Alarm limit on a value.
+///
+/// The limit is violated if the value goes below `min` or above `max` (or is NaN).
+/// A violation ends once the value is back within the limits by at least `hysteresis`.
+/// The alarm asserts once a violation has lasted `delay`.
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct Limit {
+    /// Lower limit.
+    #[tree(validate=self.validate_limit)]
+    pub min: Leaf<f32>,
+
+    /// Upper limit.
+    #[tree(validate=self.validate_limit)]
+    pub max: Leaf<f32>,
+
+    /// Hysteresis for the end of a violation.
+    ///
+    /// # Value
+    /// Any non-negative value up to half the range between `min` and `max`.
+    #[tree(validate=self.validate_limit)]
+    pub hysteresis: Leaf<f32>,
+
+    /// Minimum violation duration in seconds before the alarm asserts.
+    ///
+    /// # Value
+    /// Any non-negative value.
+    #[tree(validate=self.validate_limit)]
+    pub delay: Leaf<f32>,
+
+    /// Severity of the alarm.
+    pub severity: Leaf<Severity>,
+
+    /// Action on the outputs while the alarm is asserted. See [OutputAction].
+    pub action: OutputAction,
+}
+
+impl Default for Limit {
+    fn default() -> Self {
+        Self {
+            min: 0.0.into(),
+            max: 0.0.into(),
+            hysteresis: 0.0.into(),
+            delay: 0.0.into(),
+            severity: Default::default(),
+            action: Default::default(),
+        }
+    }
+}
+
+/// Alarm limit with an enable switch.
+#[derive(Copy, Clone, Debug, Default, Tree)]
+pub struct SwitchedLimit {
+    /// Check the limit.
+    ///
+    /// # Value
+    /// `true` or `false`
+    pub enable: Leaf<bool>,
+
+    /// The limit. See [Limit].
+    pub limit: Limit,
+}
+
+impl SwitchedLimit {
+    /// The limit if it is enabled.
+    pub fn get(&self) -> Option<&Limit> {
+        self.enable.then_some(&self.limit)
+    }
+}
+
+/// Alarm limit state.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LimitState {
+    /// The limit is violated.
+    violated: bool,
+    /// Violation duration in seconds.
+    duration: f32,
+    /// The alarm is asserted.
+    asserted: bool,
+    /// Most recent value violating the limit.
+    value: f32,
+    /// Most recently violated limit.
+    limit: f32,
+}
+
+/// Alarm limit violation details.
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Violation {
+    /// Most recent value violating the limit.
+    pub value: f32,
+    /// The violated limit.
+    pub limit: f32,
+    /// Duration of the current violation in seconds. Zero if a latched violation has ended.
+    pub duration: f32,
+    /// Severity of the alarm.
+    pub severity: Severity,
+}
+
+impl LimitState {
+    /// Whether the alarm is asserted.
+    pub fn asserted(&self) -> bool {
+        self.asserted
+    }
+
+    /// Duration of the current violation in seconds. Zero if the limit is not violated.
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    /// Clear a latched alarm.
+    ///
+    /// The alarm asserts again if the violation persists for `delay`.
+    pub fn acknowledge(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl Limit {
+    fn validate_limit(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if self.min.is_nan() || self.max.is_nan() || *self.max < *self.min {
+            return Err("Maximum below minimum");
+        }
+        if self.hysteresis.is_nan() || *self.hysteresis < 0.0 {
+            return Err("Hysteresis must be non-negative");
+        }
+        // Otherwise a violation could never end.
+        if 2.0 * *self.hysteresis > *self.max - *self.min {
+            return Err("Hysteresis exceeds half the limit range");
+        }
+        if self.delay.is_nan() || *self.delay < 0.0 {
+            return Err("Delay must be non-negative");
+        }
+        Ok(depth)
+    }
+
+    /// Run all validators.
+    pub fn validate(&mut self) -> Result<(), &'static str> {
+        self.validate_limit(0)?;
+        Ok(())
+    }
+
+    /// Update the limit state.
+    ///
+    /// # Args
+    /// * `state` - Limit state
+    /// * `value` - Lowest and highest value since the last update
+    /// * `elapsed` - Time since the last update in seconds
+    /// * `latch` - Keep the alarm asserted after the violation ends until acknowledged
+    ///
+    /// # Returns
+    /// True if the alarm is asserted.
+    pub fn update(
+        &self,
+        state: &mut LimitState,
+        value: [f32; 2],
+        elapsed: f32,
+        latch: bool,
+    ) -> bool {
+        let margin = if state.violated {
+            *self.hysteresis
+        } else {
+            0.0
+        };
+        let valid = *self.min + margin..=*self.max - margin;
+        let [low, high] = value;
+        // NaN values violate the limits.
+        state.violated = true;
+        if !valid.contains(&low) {
+            (state.value, state.limit) = (low, *self.min);
+        } else if !valid.contains(&high) {
+            (state.value, state.limit) = (high, *self.max);
+        } else {
+            state.violated = false;
+        }
+        if state.violated {
+            state.duration += elapsed;
+            state.asserted |= state.duration >= *self.delay;
+        } else {
+            state.duration = 0.0;
+            state.asserted &= latch;
+        }
+        state.asserted
+    }
+
+    /// Details of an asserted alarm.
+    ///
+    /// # Returns
+    /// `None` if the alarm is not asserted.
+    pub fn violation(&self, state: &LimitState) -> Option<Violation> {
+        state.asserted.then_some(Violation {
+            value: state.value,
+            limit: state.limit,
+            duration: state.duration,
+            severity: *self.severity,
+        })
+    }
+}
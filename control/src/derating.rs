@@ -0,0 +1,71 @@
+//! # Thermostat_EEM board temperature derating
+//!
+//! The output current range of all outputs is reduced as the board temperature approaches
+//! the hardware overtemperature threshold.
+
+use miniconf::{Leaf, Tree};
+use num_traits::Float;
+
+/// Board temperature current derating.
+///
+/// Above `start` the output current range of all outputs is scaled down linearly, reaching
+/// `min` at `end` and above.
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct Derating {
+    /// Board temperature where the derating starts.
+    ///
+    /// Units: °C
+    #[tree(validate=self.validate)]
+    pub start: Leaf<f32>,
+
+    /// Board temperature where the derating reaches `min`.
+    ///
+    /// Units: °C
+    #[tree(validate=self.validate)]
+    pub end: Leaf<f32>,
+
+    /// Output current scale factor at and above `end`.
+    ///
+    /// # Value
+    /// `[0, 1]`
+    #[tree(validate=self.validate)]
+    pub min: Leaf<f32>,
+}
+
+impl Default for Derating {
+    fn default() -> Self {
+        Self {
+            start: 60.0.into(),
+            end: 75.0.into(),
+            min: 0.0.into(),
+        }
+    }
+}
+
+impl Derating {
+    fn validate(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if (*self.end - *self.start).is_nan() || *self.end <= *self.start {
+            return Err("Derating end must be above start");
+        }
+        if !(0.0..=1.0).contains(&*self.min) {
+            return Err("Derating factor out of range [0, 1]");
+        }
+        Ok(depth)
+    }
+
+    /// Compute the derating factor.
+    ///
+    /// # Args
+    /// * `temperature` - Board temperature in °C, `None` if unavailable
+    ///
+    /// # Returns
+    /// The output current scale factor in `[min, 1]`, rounded down to steps of 1 %.
+    /// No derating without a valid temperature.
+    pub fn factor(&self, temperature: Option<f32>) -> f32 {
+        let Some(temperature) = temperature.filter(|t| !t.is_nan()) else {
+            return 1.0;
+        };
+        let x = ((temperature - *self.start) / (*self.end - *self.start)).clamp(0.0, 1.0);
+        ((1.0 - x * (1.0 - *self.min)) * 100.0).floor() / 100.0
+    }
+}
@@ -0,0 +1,337 @@
+//! # Thermostat_EEM in-loop excitation and transfer function measurement
+//!
+//! An excitation signal (stepped sine sweep, PRBS or multisine) is injected additively at the
+//! setpoint or at the output of an output channel. The excitation, the combined input and the
+//! output current are demodulated on-device at a set of frequencies. The lock-in style I/Q
+//! results allow computing the plant (input/output) and closed-loop (input/excitation or
+//! output/excitation) transfer functions.
+
+use core::f32::consts::PI;
+use miniconf::{Leaf, Tree};
+use num_traits::Float;
+use serde::Serialize;
+
+use crate::tec::MAX_CURRENT_LIMIT;
+
+/// Number of frequency points.
+pub const FREQUENCIES: usize = 16;
+
+/// Excitation waveform.
+#[derive(Copy, Clone, Default, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Waveform {
+    /// No excitation.
+    #[default]
+    Off,
+    /// Stepped sine sweep. Each frequency is excited for `duration / FREQUENCIES`.
+    Sweep,
+    /// Pseudo-random binary sequence with a chip rate of twice `f_stop`.
+    Prbs,
+    /// Sum of sines at all frequencies with Schroeder phases.
+    Multisine,
+}
+
+/// Excitation injection point.
+#[derive(Copy, Clone, Default, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Injection {
+    /// Added to the setpoint. Units of the amplitude: input
+    #[default]
+    Setpoint,
+    /// Added to the output current. Units of the amplitude: A
+    Output,
+}
+
+/// Excitation settings.
+///
+/// The measurement is repeated and its result published as long as the excitation is
+/// enabled and the output is `On`. Any change of the settings restarts the measurement.
+#[derive(Copy, Clone, Debug, PartialEq, Tree)]
+pub struct Excitation {
+    /// Excitation waveform. See [Waveform].
+    pub waveform: Leaf<Waveform>,
+
+    /// Injection point. See [Injection].
+    #[tree(validate=self.validate_amplitude)]
+    pub injection: Leaf<Injection>,
+
+    /// Peak excitation amplitude.
+    ///
+    /// Units: input or output, see [Injection]
+    ///
+    /// # Value
+    /// Any non-negative finite value, at most the maximum output current for output injection.
+    #[tree(validate=self.validate_amplitude)]
+    pub amplitude: Leaf<f32>,
+
+    /// Measurement duration in seconds.
+    #[tree(validate=self.validate)]
+    pub duration: Leaf<f32>,
+
+    /// Lowest frequency in Hz.
+    #[tree(validate=self.validate)]
+    pub f_start: Leaf<f32>,
+
+    /// Highest frequency in Hz. The frequencies are logarithmically spaced between `f_start`
+    /// and `f_stop` and rounded to multiples of the frequency resolution.
+    #[tree(validate=self.validate)]
+    pub f_stop: Leaf<f32>,
+}
+
+impl Default for Excitation {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Off.into(),
+            injection: Injection::Setpoint.into(),
+            amplitude: 0.0.into(),
+            duration: 160.0.into(),
+            f_start: 0.01.into(),
+            f_stop: 10.0.into(),
+        }
+    }
+}
+
+impl Excitation {
+    pub(crate) fn validate(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if !(*self.duration > 0.0 && *self.f_start > 0.0 && *self.f_stop >= *self.f_start) {
+            return Err("Invalid excitation duration or frequencies.");
+        }
+        Ok(depth)
+    }
+
+    pub(crate) fn validate_amplitude(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if !(self.amplitude.is_finite() && *self.amplitude >= 0.0) {
+            return Err("Excitation amplitude must be non-negative and finite.");
+        }
+        if *self.injection == Injection::Output && *self.amplitude > MAX_CURRENT_LIMIT {
+            return Err("Excitation amplitude exceeds the maximum output current.");
+        }
+        Ok(depth)
+    }
+
+    /// Split the excitation into the setpoint and output offsets.
+    ///
+    /// # Args
+    /// * `excitation` - Excitation sample
+    ///
+    /// # Returns
+    /// `(setpoint, output)` offsets
+    pub fn inject(&self, excitation: f32) -> (f32, f32) {
+        match *self.injection {
+            Injection::Setpoint => (excitation, 0.0),
+            Injection::Output => (0.0, excitation),
+        }
+    }
+
+    /// Duration of the demodulation window of each frequency in seconds.
+    fn window(&self) -> f32 {
+        match *self.waveform {
+            Waveform::Sweep => *self.duration / FREQUENCIES as f32,
+            _ => *self.duration,
+        }
+    }
+
+    /// Frequency of the k-th point in Hz.
+    fn frequency(&self, k: usize) -> f32 {
+        let resolution = self.window().recip();
+        let ratio = *self.f_stop / *self.f_start;
+        let f = *self.f_start * ratio.powf(k as f32 / (FREQUENCIES - 1) as f32);
+        (f / resolution).round().max(1.0) * resolution
+    }
+}
+
+/// Lock-in I/Q results of a transfer function measurement.
+///
+/// The I/Q values are the amplitudes of the cosine and sine components at each frequency.
+#[derive(Serialize, Copy, Clone, Debug, Default)]
+pub struct Response {
+    /// Frequencies in Hz.
+    pub frequency: [f32; FREQUENCIES],
+    /// Excitation I/Q.
+    pub excitation: [[f32; 2]; FREQUENCIES],
+    /// Combined input I/Q.
+    pub input: [[f32; 2]; FREQUENCIES],
+    /// Output current I/Q.
+    pub output: [[f32; 2]; FREQUENCIES],
+}
+
+/// Demodulator accumulators of a single frequency.
+#[derive(Copy, Clone, Debug, Default)]
+struct Bin {
+    /// Phase in turns.
+    phase: f32,
+    /// Phase increment per sample in turns.
+    step: f32,
+    /// I/Q sums of excitation, input and output.
+    iq: [[f64; 2]; 3],
+    /// Number of accumulated samples.
+    count: u32,
+}
+
+/// Excitation generator and demodulator state.
+#[derive(Copy, Clone, Debug)]
+pub struct ExcitationState {
+    config: Excitation,
+    bins: [Bin; FREQUENCIES],
+    /// Sample index within the measurement.
+    sample: u32,
+    /// Total number of samples of the measurement.
+    samples: u32,
+    /// Update period in seconds.
+    period: f32,
+    /// PRBS shift register.
+    lfsr: u16,
+    /// PRBS chip length in samples.
+    chip: u32,
+    /// Current excitation sample.
+    value: f32,
+}
+
+impl Default for ExcitationState {
+    fn default() -> Self {
+        Self {
+            config: Excitation::default(),
+            bins: Default::default(),
+            sample: 0,
+            samples: 0,
+            period: 1.0,
+            lfsr: 1,
+            chip: 1,
+            value: 0.0,
+        }
+    }
+}
+
+impl ExcitationState {
+    /// Restart the measurement with new settings.
+    fn start(&mut self, config: &Excitation, period: f32) {
+        self.config = *config;
+        self.sample = 0;
+        self.samples = ((*config.duration / period) as u32).max(FREQUENCIES as _);
+        self.period = period;
+        self.lfsr = 1;
+        self.chip = ((0.5 / (*config.f_stop * period)) as u32).max(1);
+        for (k, bin) in self.bins.iter_mut().enumerate() {
+            // Schroeder phases for a low crest factor multisine
+            let phase = match *config.waveform {
+                Waveform::Multisine => -((k * (k + 1)) as f32) / (2 * FREQUENCIES) as f32,
+                _ => 0.0,
+            };
+            *bin = Bin {
+                step: config.frequency(k) * period,
+                phase: phase - phase.floor(),
+                ..Default::default()
+            };
+        }
+    }
+
+    /// Index of the frequency demodulated at the current sample. `None` for all.
+    fn active(&self) -> Option<usize> {
+        match *self.config.waveform {
+            Waveform::Sweep => Some(
+                (self.sample as usize * FREQUENCIES / self.samples.max(1) as usize)
+                    .min(FREQUENCIES - 1),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Generate the next excitation sample.
+    ///
+    /// # Args
+    /// * `config` - Excitation settings
+    /// * `period` - Update period in seconds
+    /// * `active` - The output loop is active. The measurement restarts once it becomes active.
+    ///
+    /// # Returns
+    /// The excitation sample. Zero if the excitation is disabled or inactive.
+    pub fn update(&mut self, config: &Excitation, period: f32, active: bool) -> f32 {
+        if !active || *config.waveform == Waveform::Off {
+            self.config.waveform = Waveform::Off.into();
+            self.value = 0.0;
+            return 0.0;
+        }
+        if *config != self.config {
+            self.start(config, period);
+        }
+        let amplitude = *self.config.amplitude;
+        self.value = match *self.config.waveform {
+            Waveform::Off => 0.0,
+            Waveform::Sweep => {
+                let bin = &self.bins[self.active().unwrap()];
+                amplitude * (2.0 * PI * bin.phase).sin()
+            }
+            Waveform::Multisine => {
+                amplitude / FREQUENCIES as f32
+                    * self
+                        .bins
+                        .iter()
+                        .map(|bin| (2.0 * PI * bin.phase).sin())
+                        .sum::<f32>()
+            }
+            Waveform::Prbs => {
+                if self.sample.is_multiple_of(self.chip) {
+                    // x^15 + x^14 + 1 maximum length sequence
+                    let bit = ((self.lfsr >> 14) ^ (self.lfsr >> 13)) & 1;
+                    self.lfsr = ((self.lfsr << 1) | bit) & 0x7fff;
+                }
+                if self.lfsr & 1 == 1 {
+                    amplitude
+                } else {
+                    -amplitude
+                }
+            }
+        };
+        self.value
+    }
+
+    /// Demodulate the response to the current excitation sample.
+    ///
+    /// # Args
+    /// * `input` - Combined input
+    /// * `output` - Output current
+    ///
+    /// # Returns
+    /// The measurement result once a measurement is complete.
+    pub fn demodulate(&mut self, input: f64, output: f32) -> Option<Response> {
+        if *self.config.waveform == Waveform::Off {
+            return None;
+        }
+        let active = self.active();
+        for (k, bin) in self.bins.iter_mut().enumerate() {
+            if active.map(|a| a == k).unwrap_or(true) {
+                let (sin, cos) = (2.0 * PI * bin.phase).sin_cos();
+                for (iq, x) in bin
+                    .iq
+                    .iter_mut()
+                    .zip([self.value as f64, input, output as f64])
+                {
+                    iq[0] += x * cos as f64;
+                    iq[1] += x * sin as f64;
+                }
+                bin.count += 1;
+            }
+            bin.phase = (bin.phase + bin.step).fract();
+        }
+        self.sample += 1;
+        if self.sample < self.samples {
+            return None;
+        }
+        let mut response = Response::default();
+        for (k, bin) in self.bins.iter().enumerate() {
+            let scale = 2.0 / bin.count.max(1) as f64;
+            response.frequency[k] = bin.step / self.period;
+            for (r, iq) in [
+                &mut response.excitation[k],
+                &mut response.input[k],
+                &mut response.output[k],
+            ]
+            .into_iter()
+            .zip(bin.iq.iter())
+            {
+                *r = [(iq[0] * scale) as f32, (iq[1] * scale) as f32];
+            }
+        }
+        let config = self.config;
+        self.start(&config, self.period);
+        Some(response)
+    }
+}
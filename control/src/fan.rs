@@ -0,0 +1,174 @@
+//! # Thermostat_EEM fan control
+//!
+//! The fan duty cycle is either fixed or derived from the board temperature and the total
+//! TEC power. The tachometer speed is checked against a minimum to detect a stalled fan.
+
+use miniconf::{Leaf, Tree};
+
+/// Fan operating mode.
+#[derive(Copy, Clone, Default, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Mode {
+    /// The fan is off.
+    Off,
+    /// Fixed duty cycle, see [FanControl::duty].
+    Fixed,
+    /// Duty cycle ramps with the board temperature and the total TEC power.
+    #[default]
+    Auto,
+}
+
+/// Fan control settings.
+///
+/// In `Auto` mode the duty cycle ramps linearly from `min` to `max` over the `temperature`
+/// and over the `power` range. The larger of the two demands is applied. Below both ranges
+/// the fan is off.
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct FanControl {
+    /// Operating mode. Setting `Off` acknowledges a stalled fan fault.
+    pub mode: Leaf<Mode>,
+
+    /// Fixed duty cycle.
+    ///
+    /// # Value
+    /// `[0, 1]`
+    #[tree(validate=self.validate_duty)]
+    pub duty: Leaf<f32>,
+
+    /// Minimum duty cycle in `Auto` mode when the fan is running.
+    ///
+    /// # Value
+    /// `[0, 1]`
+    #[tree(validate=self.validate_duty)]
+    pub min: Leaf<f32>,
+
+    /// Maximum duty cycle in `Auto` mode.
+    ///
+    /// # Value
+    /// `[0, 1]`
+    #[tree(validate=self.validate_duty)]
+    pub max: Leaf<f32>,
+
+    /// Board temperature range for the `Auto` mode ramp.
+    ///
+    /// Units: °C
+    #[tree(validate=self.validate_range)]
+    pub temperature: [Leaf<f32>; 2],
+
+    /// Total TEC power range for the `Auto` mode ramp.
+    ///
+    /// Units: W
+    #[tree(validate=self.validate_range)]
+    pub power: [Leaf<f32>; 2],
+
+    /// Minimum speed of a running fan.
+    ///
+    /// Units: RPM
+    pub stall: Leaf<f32>,
+
+    /// Minimum stall duration in seconds.
+    ///
+    /// # Value
+    /// Any positive value. Infinity disables the detector.
+    pub time: Leaf<f32>,
+}
+
+impl Default for FanControl {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            duty: 0.5.into(),
+            min: 0.3.into(),
+            max: 1.0.into(),
+            temperature: [35.0.into(), 60.0.into()],
+            power: [2.0.into(), 20.0.into()],
+            stall: 300.0.into(),
+            time: 5.0.into(),
+        }
+    }
+}
+
+/// Fan stall detector state.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StallState {
+    /// Stall duration in seconds.
+    time: f32,
+}
+
+impl FanControl {
+    fn validate_duty(&mut self, depth: usize) -> Result<usize, &'static str> {
+        for duty in [*self.duty, *self.min, *self.max] {
+            if !(0.0..=1.0).contains(&duty) {
+                return Err("Duty cycle out of range [0, 1]");
+            }
+        }
+        if *self.min > *self.max {
+            return Err("Minimum duty cycle above maximum");
+        }
+        Ok(depth)
+    }
+
+    fn validate_range(&mut self, depth: usize) -> Result<usize, &'static str> {
+        for [low, high] in [self.temperature, self.power] {
+            if (*high - *low).is_nan() || *high <= *low {
+                return Err("Range must be increasing");
+            }
+        }
+        Ok(depth)
+    }
+
+    /// Run all validators.
+    pub fn validate(&mut self) -> Result<(), &'static str> {
+        self.validate_duty(0)?;
+        self.validate_range(0)?;
+        Ok(())
+    }
+
+    /// Compute the fan duty cycle.
+    ///
+    /// # Args
+    /// * `temperature` - Board temperature in °C, `None` if unavailable
+    /// * `power` - Total TEC power in Watt
+    ///
+    /// # Returns
+    /// The duty cycle in `[0, 1]`.
+    pub fn output(&self, temperature: Option<f32>, power: f32) -> f32 {
+        fn ramp([low, high]: [Leaf<f32>; 2], x: f32) -> f32 {
+            (x - *low) / (*high - *low)
+        }
+        match *self.mode {
+            Mode::Off => 0.0,
+            Mode::Fixed => *self.duty,
+            Mode::Auto => {
+                // Note: NaN inputs are ignored by `max()`.
+                let demand = temperature
+                    .map(|t| ramp(self.temperature, t))
+                    .unwrap_or(0.0)
+                    .max(ramp(self.power, power));
+                if demand > 0.0 {
+                    (*self.min + demand * (*self.max - *self.min)).min(*self.max)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// Update the stall detector.
+    ///
+    /// # Args
+    /// * `state` - Detector state
+    /// * `duty` - Fan duty cycle
+    /// * `rpm` - Measured fan speed
+    /// * `period` - Time since the last update in seconds
+    ///
+    /// # Returns
+    /// True if the fan is driven but persistently too slow.
+    pub fn stalled(&self, state: &mut StallState, duty: f32, rpm: f32, period: f32) -> bool {
+        if duty <= 0.0 || rpm >= *self.stall {
+            *state = StallState::default();
+            return false;
+        }
+        state.time += period;
+        state.time > *self.time
+    }
+}
@@ -0,0 +1,22 @@
+//! # Thermostat_EEM control path
+//!
+//! Hardware independent signal processing and control of Thermostat-EEM: sensor conversions,
+//! the output channel PID/Biquad/IIR, output current to DAC code conversion, the
+//! in-loop excitation and fan control. This crate builds on the host and comes with a thermal plant model
+//! to exercise the closed loop in tests.
+
+#![no_std]
+// The test harness links `std` whose inherent float methods shadow `num_traits::Float`.
+#![cfg_attr(test, allow(unused_imports))]
+
+pub mod alarm;
+pub mod derating;
+pub mod dt670;
+pub mod excitation;
+pub mod fan;
+pub mod output_channel;
+pub mod plant;
+pub mod rate;
+pub mod sensor;
+pub mod supply;
+pub mod tec;
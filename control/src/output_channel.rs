@@ -0,0 +1,1016 @@
+//! # Thermostat_EEM IIR wrapper.
+//!
+
+use crate::{
+    excitation::Excitation,
+    tec::{Affine, DacCode, MAX_CURRENT_LIMIT, MAX_VOLTAGE_LIMIT},
+};
+use idsp::iir;
+use miniconf::{Leaf, Tree};
+use num_traits::Float;
+
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct Pid {
+    /// Integral gain
+    ///
+    /// Units: output/input per second
+    pub ki: Leaf<f32>,
+    /// Proportional gain
+    ///
+    /// Note that this is the sign reference for all gains and limits
+    ///
+    /// Units: output/input
+    pub kp: Leaf<f32>,
+    /// Derivative gain
+    ///
+    /// Units: output/input*second
+    pub kd: Leaf<f32>,
+    /// Integral gain limit
+    ///
+    /// Units: output/input
+    pub li: Leaf<f32>,
+    /// Derivative gain limit
+    ///
+    /// Units: output/input
+    pub ld: Leaf<f32>,
+    /// Setpoint
+    ///
+    /// Units: input
+    pub setpoint: Leaf<f32>,
+    /// Output lower limit
+    ///
+    /// Units: output
+    pub min: Leaf<f32>,
+    /// Output upper limit
+    ///
+    /// Units: output
+    pub max: Leaf<f32>,
+    /// Update/sample period
+    ///
+    /// Units: seconds
+    #[tree(skip)]
+    pub period: f32,
+}
+
+impl Default for Pid {
+    fn default() -> Self {
+        Self {
+            ki: 0.0.into(),
+            kp: 0.0.into(), // positive default
+            kd: 0.0.into(),
+            li: f32::INFINITY.into(),
+            ld: f32::INFINITY.into(),
+            setpoint: 0.0.into(),
+            min: f32::NEG_INFINITY.into(),
+            max: f32::INFINITY.into(),
+            period: 1.0,
+        }
+    }
+}
+
+impl TryFrom<Pid> for iir::Biquad<f64> {
+    type Error = iir::PidError;
+    fn try_from(value: Pid) -> Result<Self, Self::Error> {
+        let mut biquad: iir::Biquad<f64> = iir::Pid::<f64>::default()
+            .period(value.period as _) // ADC sample rate (ODR) including zero-oder-holds
+            .gain(iir::Action::Ki, value.ki.copysign(*value.kp) as _)
+            .gain(iir::Action::Kp, *value.kp as _)
+            .gain(iir::Action::Kd, value.kd.copysign(*value.kp) as _)
+            .limit(
+                iir::Action::Ki,
+                if value.li.is_finite() {
+                    *value.li
+                } else {
+                    f32::INFINITY
+                }
+                .copysign(*value.kp) as _,
+            )
+            .limit(
+                iir::Action::Kd,
+                if value.ld.is_finite() {
+                    *value.ld
+                } else {
+                    f32::INFINITY
+                }
+                .copysign(*value.kp) as _,
+            )
+            .build()?
+            .into();
+        biquad.set_input_offset(-*value.setpoint as _);
+        biquad.set_min(if value.min.is_finite() {
+            *value.min
+        } else {
+            f32::NEG_INFINITY
+        } as _);
+        biquad.set_max(if value.max.is_finite() {
+            *value.max
+        } else {
+            f32::INFINITY
+        } as _);
+        Ok(biquad)
+    }
+}
+
+#[derive(
+    Copy, Clone, Default, Debug, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub enum State {
+    /// Active TEC driver and Biquad/PID.
+    On,
+    /// Hold the output.
+    Hold,
+    /// Disables the TEC driver. This implies "hold".
+    #[default]
+    Off,
+    /// Run the output current calibration routine. See [Calibration].
+    /// The state returns to `Off` when the routine is done.
+    Calibrate,
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Actuator {
+    /// Thermoelectric cooler. Bipolar current, the PID output is the TEC current.
+    #[default]
+    Tec,
+    /// Resistive heater. Unipolar current, the PID output is the heating power.
+    /// It is linearized to a current using the heater resistance.
+    Heater,
+}
+
+/// Input combination mode.
+///
+/// All modes except `Sum` operate on the unweighted temperatures of the inputs
+/// with non-zero weight.
+#[derive(Copy, Clone, Default, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Combine {
+    /// Normalized weighted sum of all inputs.
+    #[default]
+    Sum,
+    /// Maximum of the selected inputs.
+    Max,
+    /// Minimum of the selected inputs.
+    Min,
+    /// Median of the selected inputs. For an even number of inputs the
+    /// mean of the two central values is used.
+    Median,
+}
+
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct OutputChannel {
+    pub state: Leaf<State>,
+
+    /// Actuator type connected to the output.
+    ///
+    /// In `Heater` mode the PID output and limits are in units of Watt, the output current
+    /// is unipolar and the negative current limit is set to zero.
+    #[tree(validate=self.validate_pid)]
+    pub actuator: Leaf<Actuator>,
+
+    /// Heater resistance in Ohm. Only used in `Heater` mode.
+    ///
+    /// # Value
+    /// Any positive non-zero value.
+    #[tree(validate=self.validate_heater_resistance)]
+    pub heater_resistance: Leaf<f32>,
+
+    /// Track the heater resistance using the output voltage and current measured
+    /// by the internal ADC. `heater_resistance` is used until a valid measurement is available.
+    pub measure_resistance: Leaf<bool>,
+
+    /// Effective heater resistance in Ohm (configured or measured).
+    #[tree(skip)]
+    pub resistance: f32,
+
+    /// Output current range scale factor in `[0, 1]`. See [crate::derating::Derating].
+    #[tree(skip)]
+    pub derating: f32,
+
+    /// Maximum absolute output current in Ampere due to the power budget.
+    /// See [crate::supply::Supply::current_cap].
+    #[tree(skip)]
+    pub current_cap: f32,
+
+    /// Output shut down by an alarm `Off` action.
+    /// Unlike setting `state` to `Off` this does not acknowledge latched faults.
+    #[tree(skip)]
+    pub inhibit: bool,
+
+    /// Maximum absolute (positive and negative) TEC voltage in volt.
+    /// These will be clamped to the maximum of 4.3 V.
+    ///
+    /// # Value
+    /// 0.0 to 4.3
+    #[tree(validate=self.validate_voltage_limit)]
+    pub voltage_limit: Leaf<f32>,
+
+    /// PID/Biquad/IIR filter parameters
+    ///
+    /// The y limits will be clamped to the maximum output current of +-3 A.
+    #[tree(validate=self.validate_pid)]
+    pub pid: Pid,
+
+    #[tree(skip)]
+    pub iir: iir::Biquad<f64>,
+
+    /// Thermostat input channel weights. Each input of an enabled input channel
+    /// is multiplied by its weight and the accumulated output is fed into the IIR.
+    /// The weights will be internally normalized to one (sum of the absolute values)
+    /// if they are not all zero.
+    #[tree(validate=self.validate_weights)]
+    pub weights: Leaf<[[f32; 4]; 4]>,
+
+    /// Input combination mode. See [Combine].
+    pub combine: Leaf<Combine>,
+
+    /// Maximum output current slew rate in A/s.
+    /// Applied to the output current after the PID/Biquad/IIR.
+    ///
+    /// # Value
+    /// Any positive non-zero value. Infinity disables the limit.
+    #[tree(validate=self.validate_slew_rate)]
+    pub slew_rate: Leaf<f32>,
+
+    /// Soft start slew rate in A/s.
+    /// The output current ramps up from zero with at most this rate whenever the TEC driver
+    /// is released from shutdown.
+    ///
+    /// # Value
+    /// Any positive non-zero value. Infinity disables the soft start.
+    #[tree(validate=self.validate_slew_rate)]
+    pub soft_start: Leaf<f32>,
+
+    /// Thermal runaway detection. See [Runaway].
+    pub runaway: Runaway,
+
+    /// Loop settled detection. See [Settle].
+    pub settle: Settle,
+
+    /// Output current verification. See [Mismatch].
+    pub mismatch: Mismatch,
+
+    /// Output current calibration. See [Calibration].
+    #[tree(validate=self.validate_calibration)]
+    pub calibration: Calibration,
+
+    /// Excitation for transfer function measurements. See [Excitation].
+    pub excitation: Excitation,
+
+    /// Hard temperature limits on input channels.
+    ///
+    /// The output is shut down and an `Interlock` fault is latched if any of the selected
+    /// inputs goes below its minimum or above its maximum temperature (or is NaN).
+    /// The check runs on the device at every output update and does not depend on the network.
+    ///
+    /// # Path
+    /// `interlock/<adc>/<channel>`
+    /// * `<adc> := [0, 1, 2, 3]` specifies the ADC.
+    /// * `<channel> := [0, 1, 2, 3]` specifies the input channel of the ADC. Enabled limits on a
+    ///   disabled input channel are always violated.
+    ///
+    /// # Value
+    /// See [InterlockLimit]
+    pub interlock: [[InterlockLimit; 4]; 4],
+}
+
+/// Hard temperature limits on an input channel. See [OutputChannel::interlock].
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct InterlockLimit {
+    /// Check the limits.
+    ///
+    /// # Value
+    /// `true` or `false`
+    pub enable: Leaf<bool>,
+
+    /// Lower limit.
+    ///
+    /// # Value
+    /// Any value not above `max`.
+    #[tree(validate=self.validate)]
+    pub min: Leaf<f32>,
+
+    /// Upper limit.
+    ///
+    /// # Value
+    /// Any value not below `min`.
+    #[tree(validate=self.validate)]
+    pub max: Leaf<f32>,
+}
+
+impl Default for InterlockLimit {
+    fn default() -> Self {
+        Self {
+            enable: false.into(),
+            min: f32::NEG_INFINITY.into(),
+            max: f32::INFINITY.into(),
+        }
+    }
+}
+
+impl InterlockLimit {
+    fn validate(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if self.min.is_nan() || self.max.is_nan() || *self.max < *self.min {
+            return Err("Maximum below minimum");
+        }
+        Ok(depth)
+    }
+
+    /// Whether the limits are enabled and violated.
+    ///
+    /// # Args
+    /// * `temperature` - Input temperature
+    /// * `enabled` - The input channel is enabled. Enabled limits on a disabled input are violated.
+    pub fn violated(&self, temperature: f64, enabled: bool) -> bool {
+        // Note: NaN is a violation.
+        *self.enable && !(enabled && (*self.min..=*self.max).contains(&(temperature as f32)))
+    }
+}
+
+/// Thermal runaway and wrong polarity detection.
+///
+/// A runaway is flagged if the temperature moves the wrong way in response to the output
+/// current: while the output drives in one direction, the error in that direction has grown
+/// by more than `margin` above its minimum and has not returned to it for longer than `time`.
+/// This covers a TEC with reversed polarity as well as a saturated output that loses control
+/// with a growing error. An output at zero current (e.g. a heater idling at its lower limit)
+/// does not drive and is not checked.
+///
+/// `time` should exceed the time the loop takes to recover from the expected disturbances.
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct Runaway {
+    /// Minimum wrong-way excursion time in seconds.
+    ///
+    /// # Value
+    /// Any positive value. Infinity disables the detector.
+    #[tree(validate=self.validate)]
+    pub time: Leaf<f32>,
+
+    /// Error growth margin.
+    ///
+    /// Units: input
+    ///
+    /// # Value
+    /// Any non-negative finite value.
+    #[tree(validate=self.validate)]
+    pub margin: Leaf<f32>,
+}
+
+impl Default for Runaway {
+    fn default() -> Self {
+        Self {
+            time: f32::INFINITY.into(),
+            margin: 1.0.into(),
+        }
+    }
+}
+
+/// Runaway detector state.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RunawayState {
+    /// Drive direction: the sign of the output current relative to the controller sign.
+    /// Zero if the output does not drive.
+    direction: f32,
+    /// Time since the error in the drive direction was at its minimum in seconds.
+    time: f32,
+    /// Minimum error in the drive direction.
+    min_error: Option<f32>,
+}
+
+impl Runaway {
+    fn validate(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if self.time.is_nan() || *self.time <= 0.0 {
+            return Err("Runaway time must be positive.");
+        }
+        if !(self.margin.is_finite() && *self.margin >= 0.0) {
+            return Err("Runaway margin must be non-negative and finite.");
+        }
+        Ok(depth)
+    }
+
+    /// Update the runaway detector.
+    ///
+    /// # Args
+    /// * `state` - Detector state
+    /// * `error` - Current control error (input - setpoint)
+    /// * `drive` - Output current times the sign of the proportional gain. A positive drive
+    ///   reduces a positive error in a loop with correct polarity.
+    /// * `period` - Update period in seconds
+    ///
+    /// # Returns
+    /// True if a runaway is detected.
+    pub fn update(&self, state: &mut RunawayState, error: f32, drive: f32, period: f32) -> bool {
+        let direction = if drive > 0.0 {
+            1.0
+        } else if drive < 0.0 {
+            -1.0
+        } else {
+            0.0
+        };
+        if direction != state.direction {
+            *state = RunawayState {
+                direction,
+                ..Default::default()
+            };
+        }
+        if direction == 0.0 {
+            return false;
+        }
+        // The error in the drive direction grows if the temperature moves the wrong way.
+        let error = direction * error;
+        match state.min_error {
+            Some(min) if error > min => {
+                state.time += period;
+                state.time > *self.time && error > min + *self.margin
+            }
+            _ => {
+                state.min_error = Some(error);
+                state.time = 0.0;
+                false
+            }
+        }
+    }
+}
+
+/// Loop settled ("locked") detection.
+///
+/// The loop is settled if the output is `On` and the absolute error has been below
+/// `tolerance` for at least `time`.
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct Settle {
+    /// Error tolerance.
+    ///
+    /// Units: input
+    ///
+    /// # Value
+    /// Any positive finite value.
+    #[tree(validate=self.validate)]
+    pub tolerance: Leaf<f32>,
+
+    /// Minimum dwell time within the tolerance in seconds.
+    ///
+    /// # Value
+    /// Any non-negative finite value.
+    #[tree(validate=self.validate)]
+    pub time: Leaf<f32>,
+}
+
+impl Default for Settle {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.01.into(),
+            time: 1.0.into(),
+        }
+    }
+}
+
+/// Settled detector state.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SettleState {
+    /// Dwell time within the tolerance in seconds.
+    time: f32,
+}
+
+impl Settle {
+    fn validate(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if !(self.tolerance.is_finite() && *self.tolerance > 0.0) {
+            return Err("Settle tolerance must be positive and finite.");
+        }
+        if !(self.time.is_finite() && *self.time >= 0.0) {
+            return Err("Settle time must be non-negative and finite.");
+        }
+        Ok(depth)
+    }
+
+    /// Update the settled detector.
+    ///
+    /// # Args
+    /// * `state` - Detector state
+    /// * `error` - Current control error (input - setpoint)
+    /// * `active` - Loop is active
+    /// * `period` - Update period in seconds
+    ///
+    /// # Returns
+    /// True if the loop is settled.
+    pub fn update(&self, state: &mut SettleState, error: f32, active: bool, period: f32) -> bool {
+        if !(active && error.abs() < *self.tolerance) {
+            *state = SettleState::default();
+            return false;
+        }
+        if state.time < *self.time {
+            state.time += period;
+        }
+        state.time >= *self.time
+    }
+}
+
+/// Output current verification.
+///
+/// The measured TEC current is compared with the commanded output current capped by the
+/// PWM current limits. A mismatch larger than `tolerance` that persists for longer than `time`
+/// indicates an open TEC, a shorted output or a TEC driver in thermal shutdown.
+/// The check is suspended while the TEC driver is shut down or the TEC voltage is close to
+/// the voltage limit. It is evaluated at the telemetry period.
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct Mismatch {
+    /// Current tolerance in Ampere.
+    ///
+    /// # Value
+    /// Any positive finite value.
+    #[tree(validate=self.validate)]
+    pub tolerance: Leaf<f32>,
+
+    /// Minimum mismatch duration in seconds.
+    ///
+    /// # Value
+    /// Any positive value. Infinity disables the detector.
+    #[tree(validate=self.validate)]
+    pub time: Leaf<f32>,
+
+    /// Shut the TEC driver down on a mismatch fault. Otherwise the fault is only reported.
+    pub shutdown: Leaf<bool>,
+}
+
+impl Default for Mismatch {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.1.into(),
+            time: f32::INFINITY.into(),
+            shutdown: true.into(),
+        }
+    }
+}
+
+/// Current mismatch detector state.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MismatchState {
+    /// Mismatch duration in seconds.
+    time: f32,
+}
+
+impl Mismatch {
+    fn validate(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if !(self.tolerance.is_finite() && *self.tolerance > 0.0) {
+            return Err("Mismatch tolerance must be positive and finite.");
+        }
+        if self.time.is_nan() || *self.time <= 0.0 {
+            return Err("Mismatch time must be positive.");
+        }
+        Ok(depth)
+    }
+
+    /// Update the mismatch detector.
+    ///
+    /// # Args
+    /// * `state` - Detector state
+    /// * `error` - Commanded minus measured current
+    /// * `active` - The check is active
+    /// * `period` - Time since the last update in seconds
+    ///
+    /// # Returns
+    /// True if a persistent mismatch is detected.
+    pub fn update(&self, state: &mut MismatchState, error: f32, active: bool, period: f32) -> bool {
+        // Note: NaN errors count as mismatch.
+        if !active || error.abs() <= *self.tolerance {
+            *state = MismatchState::default();
+            return false;
+        }
+        state.time += period;
+        state.time > *self.time
+    }
+}
+
+/// Output current calibration.
+///
+/// The DAC path maps the nominal output current to the current delivered by the TEC driver.
+/// The current sense path maps the delivered current to the nominal current measured by
+/// the internal ADC.
+///
+/// The calibration routine (output `state` set to `Calibrate`) steps the output through
+/// currents up to `current` into a reference resistor `load` connected instead of the TEC.
+/// The slew rate limit and soft start do not apply during calibration. The delivered current is derived from the measured output voltage. The fitted
+/// coefficients are applied and stored in flash.
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct Calibration {
+    /// DAC path calibration.
+    pub dac: Affine,
+
+    /// Current sense path calibration.
+    pub sense: Affine,
+
+    /// Reference load resistance in Ohm.
+    pub load: Leaf<f32>,
+
+    /// Maximum absolute calibration current in Ampere.
+    /// Also limited by the voltage limit across the load.
+    pub current: Leaf<f32>,
+
+    /// Output current while the routine is running.
+    #[tree(skip)]
+    pub drive: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            dac: Default::default(),
+            sense: Default::default(),
+            load: 1.0.into(),
+            current: 1.0.into(),
+            drive: 0.0,
+        }
+    }
+}
+
+/// Output channel fault.
+///
+/// A latched fault keeps the TEC driver of the output channel in shutdown until it is
+/// acknowledged by setting the output `state` to `Off`.
+/// A `Current` fault only shuts the driver down if configured, see [Mismatch].
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Fault {
+    /// Thermal runaway or wrong TEC polarity. See [Runaway].
+    Runaway,
+    /// Persistent mismatch between commanded and measured output current. See [Mismatch].
+    Current,
+    /// The output current is NaN, e.g. due to a NaN input.
+    Nan,
+    /// An input is outside of its interlock limits. See [OutputChannel::interlock].
+    Interlock,
+    /// A supply rail is below its undervoltage threshold. See [crate::supply::Supply].
+    Supply,
+}
+
+/// Output current slew rate limiter state.
+#[derive(Copy, Clone, Debug)]
+pub struct Slew {
+    /// Last output current in Ampere.
+    current: f32,
+    /// Soft start ramp in progress.
+    soft_start: bool,
+}
+
+impl Default for Slew {
+    fn default() -> Self {
+        Self {
+            current: 0.0,
+            soft_start: true,
+        }
+    }
+}
+
+impl Default for OutputChannel {
+    fn default() -> Self {
+        let mut s = Self {
+            state: State::Off.into(),
+            actuator: Actuator::Tec.into(),
+            heater_resistance: 1.0.into(),
+            measure_resistance: false.into(),
+            resistance: 1.0,
+            derating: 1.0,
+            current_cap: f32::INFINITY,
+            inhibit: false,
+            voltage_limit: MAX_VOLTAGE_LIMIT.into(),
+            pid: Pid {
+                period: 1.0 / 1007.0,
+                max: 0.01.into(),
+                min: (-0.01).into(),
+                setpoint: 25.0.into(),
+                ..Default::default()
+            },
+            iir: Default::default(),
+            weights: Default::default(),
+            combine: Combine::Sum.into(),
+            slew_rate: f32::INFINITY.into(),
+            soft_start: 1.0.into(),
+            runaway: Default::default(),
+            settle: Default::default(),
+            mismatch: Default::default(),
+            calibration: Default::default(),
+            excitation: Default::default(),
+            interlock: Default::default(),
+        };
+        s.validate().unwrap();
+        s
+    }
+}
+
+impl OutputChannel {
+    /// Minimum absolute output current in Ampere for a valid resistance measurement.
+    pub const MIN_MEASUREMENT_CURRENT: f32 = 0.05;
+
+    /// Relative voltage margin below the voltage limit for the current verification.
+    const VOLTAGE_MARGIN: f32 = 0.95;
+
+    /// Combine the input temperatures according to the weights and the combination mode.
+    pub fn input(&self, temperatures: &[[f64; 4]; 4]) -> f64 {
+        let inputs = temperatures
+            .as_flattened()
+            .iter()
+            .zip(self.weights.as_flattened().iter());
+        let mut selected = inputs.clone().filter(|(_, w)| **w != 0.0).map(|(t, _)| *t);
+        match *self.combine {
+            Combine::Sum => inputs.map(|(t, w)| t * *w as f64).sum(),
+            Combine::Max => selected.reduce(f64::max).unwrap_or_default(),
+            Combine::Min => selected.reduce(f64::min).unwrap_or_default(),
+            Combine::Median => {
+                let mut buf = [0.0; 16];
+                let mut n = 0;
+                for (b, t) in buf.iter_mut().zip(&mut selected) {
+                    *b = t;
+                    n += 1;
+                }
+                let buf = &mut buf[..n];
+                buf.sort_unstable_by(f64::total_cmp);
+                match n {
+                    0 => 0.0,
+                    n if n % 2 == 1 => buf[n / 2],
+                    n => 0.5 * (buf[n / 2 - 1] + buf[n / 2]),
+                }
+            }
+        }
+    }
+
+    /// compute iir state from the combined input and return the new output current
+    pub fn update(&mut self, input: f64, iir_state: &mut [f64; 4]) -> f64 {
+        let iir = if *self.state == State::On {
+            &self.iir
+        } else {
+            &iir::Biquad::HOLD
+        };
+        self.output_current(iir.update(iir_state, input))
+    }
+
+    /// Convert the IIR output to an output current.
+    fn output_current(&self, y: f64) -> f64 {
+        match *self.actuator {
+            Actuator::Tec => y,
+            // Linearize the heating power P = R I² to a current.
+            Actuator::Heater => (y.max(0.0) / self.resistance as f64)
+                .sqrt()
+                .min(Self::max_current() as _),
+        }
+    }
+
+    /// Output current range `[min, max]` in Ampere, scaled by the derating factor and
+    /// capped by the power budget.
+    pub fn current_range(&self) -> [f32; 2] {
+        [self.iir.min(), self.iir.max()].map(|y| {
+            (self.output_current(y) as f32 * self.derating)
+                .max(-self.current_cap)
+                .min(self.current_cap)
+        })
+    }
+
+    /// Check the output for a thermal runaway.
+    ///
+    /// # Args
+    /// * `state` - Runaway detector state
+    /// * `input` - Combined input
+    /// * `current` - Output current before slew rate limiting
+    ///
+    /// # Returns
+    /// True if a runaway is detected.
+    pub fn runaway(&self, state: &mut RunawayState, input: f64, current: f32) -> bool {
+        let drive = if *self.state == State::On {
+            current * 1f32.copysign(*self.pid.kp)
+        } else {
+            0.0
+        };
+        let error = (input - *self.pid.setpoint as f64) as f32;
+        self.runaway.update(state, error, drive, self.pid.period)
+    }
+
+    /// Check whether the loop has settled.
+    ///
+    /// # Args
+    /// * `state` - Settled detector state
+    /// * `input` - Combined input
+    /// * `shutdown` - TEC driver shutdown state
+    ///
+    /// # Returns
+    /// True if the loop is settled.
+    pub fn settled(&self, state: &mut SettleState, input: f64, shutdown: bool) -> bool {
+        let active = *self.state == State::On && !shutdown;
+        let error = (input - *self.pid.setpoint as f64) as f32;
+        self.settle.update(state, error, active, self.pid.period)
+    }
+
+    /// Check the measured TEC current against the commanded output current.
+    ///
+    /// # Args
+    /// * `state` - Mismatch detector state
+    /// * `current` - Commanded output current
+    /// * `measured` - Measured TEC current
+    /// * `voltage` - Measured TEC voltage
+    /// * `shutdown` - TEC driver shutdown state
+    /// * `period` - Time since the last check in seconds
+    ///
+    /// # Returns
+    /// True if a persistent mismatch is detected.
+    pub fn mismatch(
+        &self,
+        state: &mut MismatchState,
+        current: f32,
+        measured: f32,
+        voltage: f32,
+        shutdown: bool,
+        period: f32,
+    ) -> bool {
+        // The TEC driver caps the current at the PWM current limits.
+        let [max, min] = self.current_limits();
+        let expected = current.max(min).min(max);
+        // Close to the voltage limit the TEC driver can not deliver the current.
+        let active = !shutdown && voltage.abs() < Self::VOLTAGE_MARGIN * *self.voltage_limit;
+        self.mismatch
+            .update(state, expected - measured, active, period)
+    }
+
+    /// Check the interlock limits.
+    ///
+    /// # Args
+    /// * `temperatures` - Input temperatures `[<adc>][<channel>]`
+    /// * `enabled` - Enabled input channels `[<adc>][<channel>]`
+    ///
+    /// # Returns
+    /// True if any input with enabled limits violates them or is disabled.
+    pub fn interlock(&self, temperatures: &[[f64; 4]; 4], enabled: &[[bool; 4]; 4]) -> bool {
+        self.interlock
+            .as_flattened()
+            .iter()
+            .zip(temperatures.as_flattened().iter())
+            .zip(enabled.as_flattened().iter())
+            .any(|((limits, t), enabled)| limits.violated(*t, *enabled))
+    }
+
+    /// Whether a fault shuts down the TEC driver.
+    pub fn fault_shutdown(&self, fault: Fault) -> bool {
+        match fault {
+            Fault::Current => *self.mismatch.shutdown,
+            _ => true,
+        }
+    }
+
+    /// Apply the slew rate limit and the soft start ramp to the output current.
+    ///
+    /// # Args
+    /// * `slew` - Slew rate limiter state
+    /// * `current` - Output current target
+    /// * `shutdown` - TEC driver shutdown state. The output is zero in shutdown and
+    ///   ramps up after the driver is released.
+    pub fn slew(&self, slew: &mut Slew, current: f32, shutdown: bool) -> f32 {
+        if shutdown {
+            *slew = Slew::default();
+            return 0.0;
+        }
+        let rate = if slew.soft_start {
+            self.slew_rate.min(*self.soft_start)
+        } else {
+            *self.slew_rate
+        };
+        let step = rate * self.pid.period;
+        // Note: max/min (unlike clamp) do not panic on NaN.
+        let next = current.max(slew.current - step).min(slew.current + step);
+        if next == current {
+            slew.soft_start = false;
+        }
+        slew.current = next;
+        next
+    }
+
+    /// Update the heater resistance estimate from the measured output voltage and current.
+    pub fn measure_resistance(&mut self, voltage: f32, current: f32) {
+        if *self.actuator != Actuator::Heater
+            || !*self.measure_resistance
+            || current.abs() < Self::MIN_MEASUREMENT_CURRENT
+        {
+            return;
+        }
+        let resistance = (voltage / current).abs();
+        if resistance.is_finite() && resistance > 0.0 {
+            self.resistance = resistance;
+        }
+    }
+
+    /// Validate all settings and build the IIR.
+    ///
+    /// This is done by the settings validators on every change. It only needs to be called
+    /// after fields have been modified directly.
+    pub fn validate(&mut self) -> Result<(), &'static str> {
+        self.validate_heater_resistance(0)?;
+        self.validate_voltage_limit(0)?;
+        self.validate_slew_rate(0)?;
+        self.validate_weights(0)?;
+        self.validate_calibration(0)?;
+        self.settle.validate(0)?;
+        self.mismatch.validate(0)?;
+        self.runaway.validate(0)?;
+        self.excitation.validate(0)?;
+        self.excitation.validate_amplitude(0)?;
+        Ok(())
+    }
+
+    /// Build the IIR after the PID settings (e.g. the setpoint) have been modified directly.
+    ///
+    /// Unlike [OutputChannel::validate] this keeps the measured heater resistance.
+    pub fn build_pid(&mut self) -> Result<(), &'static str> {
+        self.validate_pid(0)?;
+        Ok(())
+    }
+
+    /// Maximum absolute output current.
+    fn max_current() -> f32 {
+        DacCode::MAX_CURRENT.min(MAX_CURRENT_LIMIT)
+    }
+
+    fn validate_pid(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if let Ok(iir) = self.pid.try_into() {
+            self.iir = iir;
+        } else {
+            return Err("Pid build failure, update not applied.");
+        }
+        let range = Self::max_current();
+        let (min, max) = match *self.actuator {
+            Actuator::Tec => (-range, range),
+            Actuator::Heater => (0.0, range * range * *self.heater_resistance),
+        };
+        self.iir.set_max(self.iir.max().clamp(min as _, max as _));
+        self.iir.set_min(self.iir.min().clamp(min as _, max as _));
+        Ok(depth)
+    }
+
+    fn validate_heater_resistance(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if !(self.heater_resistance.is_finite() && *self.heater_resistance > 0.0) {
+            return Err("Heater resistance must be positive and finite.");
+        }
+        self.resistance = *self.heater_resistance;
+        self.validate_pid(depth)
+    }
+
+    fn validate_slew_rate(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if !(*self.slew_rate > 0.0 && *self.soft_start > 0.0) {
+            return Err("Slew rates must be positive.");
+        }
+        Ok(depth)
+    }
+
+    fn validate_voltage_limit(&mut self, depth: usize) -> Result<usize, &'static str> {
+        *self.voltage_limit = (*self.voltage_limit).clamp(0.0, MAX_VOLTAGE_LIMIT);
+        Ok(depth)
+    }
+
+    fn validate_calibration(&mut self, depth: usize) -> Result<usize, &'static str> {
+        let c = &self.calibration;
+        if !(*c.dac.gain > 0.0 && *c.sense.gain > 0.0 && *c.load > 0.0 && *c.current > 0.0) {
+            return Err("Calibration gains, load and current must be positive.");
+        }
+        Ok(depth)
+    }
+
+    /// Calibration currents in ascending order.
+    ///
+    /// Evenly spaced within the calibration current, the voltage limit across the load and
+    /// the maximum current. Unipolar for a `Heater` actuator.
+    pub fn calibration_currents<const N: usize>(&self) -> [f32; N] {
+        let max = self
+            .calibration
+            .current
+            .min(Self::VOLTAGE_MARGIN * *self.voltage_limit / *self.calibration.load)
+            .min(Self::max_current());
+        let min = if *self.actuator == Actuator::Heater {
+            0.0
+        } else {
+            -max
+        };
+        core::array::from_fn(|i| min + (max - min) * i as f32 / (N - 1) as f32)
+    }
+
+    fn validate_weights(&mut self, depth: usize) -> Result<usize, &'static str> {
+        let divisor: f32 = self.weights.as_flattened().iter().map(|w| w.abs()).sum();
+        // Note: The weights which are not 'None' should always affect an enabled channel and therefore count for normalization.
+        if divisor != 0.0 {
+            let n = divisor.recip();
+            for w in self.weights.as_flattened_mut().iter_mut() {
+                *w *= n;
+            }
+        }
+        Ok(depth)
+    }
+
+    pub fn current_limits(&self) -> [f32; 2] {
+        let [min, max] = if *self.state == State::Calibrate {
+            self.calibration_currents()
+        } else {
+            self.current_range()
+        };
+        [
+            // give 5% extra headroom for PWM current limits
+            // [MAX_CURRENT_LIMIT] + 5% is still below 100% duty cycle for the PWM limits and therefore OK.
+            // Might not be OK for a different shunt resistor or different PWM setup.
+            (max + 0.05 * MAX_CURRENT_LIMIT).max(0.),
+            if *self.actuator == Actuator::Heater {
+                0.
+            } else {
+                (min - 0.05 * MAX_CURRENT_LIMIT).min(0.)
+            },
+        ]
+    }
+}
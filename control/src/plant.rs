@@ -0,0 +1,190 @@
+//! # Thermostat_EEM thermal plant models
+//!
+//! Simple models of the thermal load of an output channel. They are used to simulate the
+//! closed loop on the host.
+//!
+//! Sign convention: A positive output current cools the load. This matches a positive
+//! proportional gain (see [crate::output_channel::Pid]).
+
+use num_traits::Float;
+
+/// Zero degree Celsius in Kelvin.
+const ZERO_C: f64 = 273.15;
+
+/// First order plus dead time plant.
+///
+/// `tau dT/dt = ambient + gain I(t - N period) - T`
+///
+/// The dead time is `N` update periods.
+#[derive(Copy, Clone, Debug)]
+pub struct Fopdt<const N: usize> {
+    /// Static gain.
+    ///
+    /// Units: K/A
+    pub gain: f64,
+    /// Time constant.
+    ///
+    /// Units: s
+    pub tau: f64,
+    /// Ambient temperature.
+    ///
+    /// Units: °C
+    pub ambient: f64,
+    /// Load temperature.
+    ///
+    /// Units: °C
+    pub temperature: f64,
+    /// Current delay line.
+    delay: [f64; N],
+    /// Delay line index.
+    index: usize,
+}
+
+impl<const N: usize> Fopdt<N> {
+    /// Create a new plant in equilibrium at ambient temperature.
+    ///
+    /// # Args
+    /// * `gain` - Static gain in K/A. Negative for a cooling current.
+    /// * `tau` - Time constant in seconds
+    /// * `ambient` - Ambient temperature
+    pub fn new(gain: f64, tau: f64, ambient: f64) -> Self {
+        Self {
+            gain,
+            tau,
+            ambient,
+            temperature: ambient,
+            delay: [0.0; N],
+            index: 0,
+        }
+    }
+
+    /// Advance the plant by one period.
+    ///
+    /// The current is held constant during the period (zero-order hold).
+    ///
+    /// # Args
+    /// * `current` - Output current in Ampere
+    /// * `period` - Update period in seconds
+    ///
+    /// # Returns
+    /// The new load temperature.
+    pub fn update(&mut self, current: f64, period: f64) -> f64 {
+        let current = if let Some(delayed) = self.delay.get_mut(self.index) {
+            self.index = (self.index + 1) % N;
+            core::mem::replace(delayed, current)
+        } else {
+            current
+        };
+        let target = self.ambient + self.gain * current;
+        self.temperature = target + (self.temperature - target) * (-period / self.tau).exp();
+        self.temperature
+    }
+}
+
+/// Two node thermal RC network with a Peltier element.
+///
+/// The load (cold side node) and the heat sink (hot side node) each have a heat capacity
+/// and a thermal conductance to ambient. The Peltier element between them pumps heat
+/// proportional to the current, dissipates Joule heat (half of it to either side) and
+/// leaks heat from the hot to the cold side:
+///
+/// * `q_cold = S I T_cold - R I²/2 - K (T_hot - T_cold)`
+/// * `q_hot = S I T_hot + R I²/2 - K (T_hot - T_cold)`
+#[derive(Copy, Clone, Debug)]
+pub struct Peltier {
+    /// Seebeck coefficient.
+    ///
+    /// Units: V/K
+    pub seebeck: f64,
+    /// Electrical resistance.
+    ///
+    /// Units: Ohm
+    pub resistance: f64,
+    /// Thermal conductance between the hot and the cold side.
+    ///
+    /// Units: W/K
+    pub conductance: f64,
+    /// Load heat capacity.
+    ///
+    /// Units: J/K
+    pub load_capacity: f64,
+    /// Load thermal conductance to ambient.
+    ///
+    /// Units: W/K
+    pub load_conductance: f64,
+    /// Heat sink heat capacity.
+    ///
+    /// Units: J/K
+    pub sink_capacity: f64,
+    /// Heat sink thermal conductance to ambient.
+    ///
+    /// Units: W/K
+    pub sink_conductance: f64,
+    /// Ambient temperature.
+    ///
+    /// Units: °C
+    pub ambient: f64,
+    /// Load temperature.
+    ///
+    /// Units: °C
+    pub temperature: f64,
+    /// Heat sink temperature.
+    ///
+    /// Units: °C
+    pub sink: f64,
+}
+
+impl Default for Peltier {
+    /// A small TEC module with a 10 J/K load on a heat sink.
+    fn default() -> Self {
+        Self {
+            seebeck: 0.05,
+            resistance: 2.0,
+            conductance: 0.5,
+            load_capacity: 10.0,
+            load_conductance: 0.05,
+            sink_capacity: 200.0,
+            sink_conductance: 2.0,
+            ambient: 25.0,
+            temperature: 25.0,
+            sink: 25.0,
+        }
+    }
+}
+
+impl Peltier {
+    /// Advance the plant by one period (forward Euler).
+    ///
+    /// The period must be small compared to the thermal time constants.
+    ///
+    /// # Args
+    /// * `current` - Output current in Ampere
+    /// * `period` - Update period in seconds
+    ///
+    /// # Returns
+    /// The new load temperature.
+    pub fn update(&mut self, current: f64, period: f64) -> f64 {
+        let (cold, hot) = (self.temperature + ZERO_C, self.sink + ZERO_C);
+        let joule = 0.5 * self.resistance * current * current;
+        let leak = self.conductance * (hot - cold);
+        let q_cold = self.seebeck * current * cold - joule - leak;
+        let q_hot = self.seebeck * current * hot + joule - leak;
+        self.temperature += period
+            * (self.load_conductance * (self.ambient - self.temperature) - q_cold)
+            / self.load_capacity;
+        self.sink += period * (self.sink_conductance * (self.ambient - self.sink) + q_hot)
+            / self.sink_capacity;
+        self.temperature
+    }
+
+    /// Terminal voltage at a given current.
+    ///
+    /// # Args
+    /// * `current` - Output current in Ampere
+    ///
+    /// # Returns
+    /// The TEC voltage in Volt.
+    pub fn voltage(&self, current: f64) -> f64 {
+        self.seebeck * (self.sink - self.temperature) + self.resistance * current
+    }
+}
@@ -0,0 +1,67 @@
+//! # Thermostat_EEM rate of change estimation
+//!
+//! The rate of change of an input is the difference of the input means over consecutive
+//! averaging windows, low-pass filtered. Averaging over windows makes the estimate independent
+//! of the sample rate and of the timer resolution.
+
+/// Filtered rate of change estimator state.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Rate {
+    /// Start of the current window in milliseconds.
+    start: Option<u32>,
+    /// Sum of the samples in the current window.
+    sum: f64,
+    /// Number of samples in the current window.
+    count: u32,
+    /// Mean of the previous window.
+    mean: Option<f64>,
+    /// Filtered rate of change in units per second.
+    rate: Option<f32>,
+}
+
+impl Rate {
+    /// Averaging window in milliseconds.
+    pub const WINDOW: u32 = 100;
+    /// Low-pass filter time constant in seconds.
+    pub const TAU: f32 = 1.0;
+
+    /// Add a sample.
+    ///
+    /// A NaN sample restarts the estimation.
+    ///
+    /// # Args
+    /// * `x` - Sample
+    /// * `now` - Time in milliseconds
+    ///
+    /// # Returns
+    /// The filtered rate of change in units per second. `None` until two windows are complete.
+    pub fn update(&mut self, x: f64, now: u32) -> Option<f32> {
+        if x.is_nan() {
+            *self = Self::default();
+            return None;
+        }
+        let start = *self.start.get_or_insert(now);
+        self.sum += x;
+        self.count += 1;
+        let elapsed = now.wrapping_sub(start);
+        if elapsed >= Self::WINDOW {
+            let mean = self.sum / self.count as f64;
+            let dt = elapsed as f32 * 1e-3;
+            if let Some(last) = self.mean {
+                let rate = (mean - last) as f32 / dt;
+                let alpha = (dt / Self::TAU).min(1.0);
+                self.rate = Some(self.rate.map_or(rate, |r| r + alpha * (rate - r)));
+            }
+            self.mean = Some(mean);
+            self.start = Some(now);
+            self.sum = 0.0;
+            self.count = 0;
+        }
+        self.rate
+    }
+
+    /// The filtered rate of change in units per second.
+    pub fn rate(&self) -> Option<f32> {
+        self.rate
+    }
+}
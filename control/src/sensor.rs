@@ -0,0 +1,165 @@
+//! # Thermostat_EEM sensor conversions
+//!
+//! Conversion of AD7172 ADC codes to temperatures for the supported sensor types.
+
+use miniconf::{Leaf, Tree};
+use num_traits::float::Float;
+use strum::{AsRefStr, EnumString};
+
+/// A type representing an ADC sample.
+/// Might be extended to support different input types (other NTCs, ref resistors etc.) in the future.
+#[derive(Copy, Clone, Debug)]
+pub struct AdcCode(u32);
+
+impl From<u32> for AdcCode {
+    /// Construct an ADC code from a provided binary (ADC-formatted) code.
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<AdcCode> for u32 {
+    fn from(code: AdcCode) -> u32 {
+        code.0
+    }
+}
+
+impl From<AdcCode> for f32 {
+    fn from(value: AdcCode) -> Self {
+        // Unchanged ADC GAIN and OFFSET registers (default reset values)
+        const GAIN: f32 = 0x555555 as _; // Default ADC gain from datasheet.
+                                         // ADC relative full scale per LSB
+                                         // Inverted equation from datasheet p. 40 with V_Ref normalized to 1
+        const FS_PER_LSB: f32 = 0x400000 as f32 / (2.0 * (1 << 23) as f32 * GAIN * 0.75);
+        value.0 as Self * FS_PER_LSB
+    }
+}
+
+pub trait Convert {
+    fn convert(&self, code: AdcCode) -> f64;
+}
+
+/// Relative_voltage * gain + offset
+///
+/// Use also for RTD
+#[derive(Clone, Copy, Debug, Tree)]
+pub struct Linear {
+    /// Units: output
+    offset: Leaf<f32>,
+    /// Units: output/input
+    gain: Leaf<f32>,
+}
+
+impl Linear {
+    pub fn new(offset: f32, gain: f32) -> Self {
+        Self {
+            offset: offset.into(),
+            gain: gain.into(),
+        }
+    }
+}
+
+impl Convert for Linear {
+    fn convert(&self, code: AdcCode) -> f64 {
+        (f32::from(code) * *self.gain) as f64 + *self.offset as f64
+    }
+}
+
+impl Default for Linear {
+    fn default() -> Self {
+        Self {
+            offset: 0.0.into(),
+            gain: 1.0.into(),
+        }
+    }
+}
+
+/// Beta equation (Steinhart-Hart with c=0)
+#[derive(Clone, Copy, Debug, Tree)]
+pub struct Ntc {
+    t0_inv: Leaf<f32>,   // inverse reference temperature (1/K)
+    r_rel: Leaf<f32>,    // reference resistor over NTC resistance at t0,
+    beta_inv: Leaf<f32>, // inverse beta
+}
+
+impl Ntc {
+    pub fn new(t0: f32, r0: f32, r_ref: f32, beta: f32) -> Self {
+        Self {
+            t0_inv: (1.0 / (t0 + ZERO_C)).into(),
+            r_rel: (r_ref / r0).into(),
+            beta_inv: (1.0 / beta).into(),
+        }
+    }
+}
+
+impl Convert for Ntc {
+    fn convert(&self, code: AdcCode) -> f64 {
+        // A f32 output dataformat leads to an output quantization of about 31 uK at T0.
+        // Additionally there is some error (in addition to the re-quantization) introduced during the
+        // various computation steps. If the input data has less than about 5 bit RMS noise, f32 should be
+        // avoided. Input values must not close to minimum/maximum (~1000 codes difference)
+        // https://en.wikipedia.org/wiki/Thermistor#B_or_%CE%B2_parameter_equation
+        let relative_voltage = f32::from(code) as f64;
+        let relative_resistance = relative_voltage / (1.0 - relative_voltage) * *self.r_rel as f64;
+        (*self.t0_inv as f64 + *self.beta_inv as f64 * relative_resistance.ln()).recip()
+            - ZERO_C as f64
+    }
+}
+
+impl Default for Ntc {
+    fn default() -> Self {
+        Self::new(25., 10.0e3, 10.0e3, 3988.)
+    }
+}
+
+/// DT-670 Silicon diode
+#[derive(Clone, Copy, Debug, Tree)]
+pub struct Dt670 {
+    v_ref: Leaf<f32>, // effective reference voltage (V)
+}
+
+impl Default for Dt670 {
+    fn default() -> Self {
+        Self { v_ref: 2.5.into() }
+    }
+}
+
+impl Convert for Dt670 {
+    fn convert(&self, code: AdcCode) -> f64 {
+        let voltage = f32::from(code) * *self.v_ref;
+        const CURVE: &[(f32, f32, f32)] = &crate::dt670::CURVE;
+        // This is clearly simplistic.
+        // It is discontinuous at LUT jumps due to dvdt precision.
+        // Should use proper interpolation, there are some crates.
+        // Note: The voltage decreases with temperature along the curve.
+        let idx = CURVE.partition_point(|&(_, v, _)| v > voltage);
+        let (t, v, dvdt) = CURVE.get(idx).or(CURVE.last()).unwrap();
+        (t + (voltage - v) * 1.0e3 / dvdt) as f64
+    }
+}
+
+/// ADC configuration structure.
+#[derive(Clone, Copy, Debug, Tree, EnumString, AsRefStr)]
+pub enum Sensor {
+    Linear(Linear),
+    Ntc(Ntc),
+    Dt670(Dt670),
+}
+
+const ZERO_C: f32 = 273.15; // 0°C in °K
+
+impl Default for Sensor {
+    fn default() -> Self {
+        Self::Linear(Linear::default())
+    }
+}
+
+impl Sensor {
+    pub fn convert(&self, code: AdcCode) -> f64 {
+        match self {
+            Self::Linear(linear) => linear.convert(code),
+            Self::Ntc(ntc) => ntc.convert(code),
+            Self::Dt670(dt670) => dt670.convert(code),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! # Thermostat_EEM supply monitoring
+//!
+//! Undervoltage detection on the supply rails and input power budgeting. The budget depends
+//! on the power source (external supply or Power over Ethernet class).
+
+use miniconf::{Leaf, Tree};
+use num_traits::Float;
+
+/// Supply rail thresholds and input power budget.
+///
+/// A supply rail below its threshold latches a `Supply` fault on all active outputs.
+///
+/// The output current of the active outputs is capped such that the input power stays within
+/// the budget for the current power source even at their voltage limits. The cap is
+/// determined whenever the settings are applied, i.e. before outputs are enabled.
+///
+/// If the input power still exceeds the budget, the output current ranges of all outputs are
+/// scaled down. The scale recovers once the input power is sufficiently below the budget.
+#[derive(Copy, Clone, Debug, Tree)]
+pub struct Supply {
+    /// 3.3 V rail undervoltage threshold in Volt.
+    ///
+    /// # Value
+    /// Any non-negative finite value. Zero disables the check.
+    #[tree(validate=self.validate_thresholds)]
+    pub p3v3: Leaf<f32>,
+
+    /// 5 V rail undervoltage threshold in Volt.
+    #[tree(validate=self.validate_thresholds)]
+    pub p5v: Leaf<f32>,
+
+    /// 12 V rail undervoltage threshold in Volt.
+    #[tree(validate=self.validate_thresholds)]
+    pub p12v: Leaf<f32>,
+
+    /// Input power budget with an external supply in Watt.
+    ///
+    /// # Value
+    /// Any positive value. Infinity disables the budget.
+    #[tree(validate=self.validate_budget)]
+    pub external: Leaf<f32>,
+
+    /// Input power budget with 802.3af (12.95 W) Power over Ethernet in Watt.
+    #[tree(validate=self.validate_budget)]
+    pub poe_af: Leaf<f32>,
+
+    /// Input power budget with 802.3at (25.5 W) Power over Ethernet in Watt.
+    #[tree(validate=self.validate_budget)]
+    pub poe_at: Leaf<f32>,
+
+    /// Input power without output current in Watt.
+    ///
+    /// # Value
+    /// Any non-negative finite value.
+    #[tree(validate=self.validate_budget)]
+    pub quiescent: Leaf<f32>,
+
+    /// Efficiency of the TEC drivers from input power to output power.
+    ///
+    /// # Value
+    /// `(0, 1]`
+    #[tree(validate=self.validate_budget)]
+    pub efficiency: Leaf<f32>,
+}
+
+impl Default for Supply {
+    fn default() -> Self {
+        Self {
+            p3v3: 3.0.into(),
+            p5v: 4.5.into(),
+            p12v: 10.0.into(),
+            external: f32::INFINITY.into(),
+            // PD power minus conversion losses
+            poe_af: 11.0.into(),
+            poe_at: 22.0.into(),
+            quiescent: 3.0.into(),
+            efficiency: 0.8.into(),
+        }
+    }
+}
+
+/// Power budget throttle state.
+#[derive(Copy, Clone, Debug)]
+pub struct Throttle {
+    /// Output current scale factor.
+    scale: f32,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self { scale: 1.0 }
+    }
+}
+
+impl Supply {
+    /// Relative margin below the budget before the scale recovers.
+    const MARGIN: f32 = 0.9;
+    /// Scale recovery factor per update.
+    const RECOVERY: f32 = 1.05;
+
+    fn validate_thresholds(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if ![*self.p3v3, *self.p5v, *self.p12v]
+            .iter()
+            .all(|t| t.is_finite() && *t >= 0.0)
+        {
+            return Err("Undervoltage thresholds must be non-negative and finite.");
+        }
+        Ok(depth)
+    }
+
+    fn validate_budget(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if !(*self.external > 0.0 && *self.poe_af > 0.0 && *self.poe_at > 0.0) {
+            return Err("Power budget must be positive.");
+        }
+        if !(self.quiescent.is_finite() && *self.quiescent >= 0.0) {
+            return Err("Quiescent power must be non-negative and finite.");
+        }
+        if !(*self.efficiency > 0.0 && *self.efficiency <= 1.0) {
+            return Err("Efficiency out of range (0, 1]");
+        }
+        Ok(depth)
+    }
+
+    /// Output current cap for the power budget.
+    ///
+    /// The power available to the outputs is shared equally among the active outputs.
+    /// The output power is at most the current times the voltage limit.
+    ///
+    /// # Args
+    /// * `budget` - Input power budget in Watt
+    /// * `voltage_limits` - Voltage limit of each active output in Volt. `None` for inactive
+    ///   outputs.
+    ///
+    /// # Returns
+    /// The maximum absolute output current of each output in Ampere. Infinite without a budget
+    /// or for inactive outputs.
+    pub fn current_cap(&self, budget: f32, voltage_limits: [Option<f32>; 4]) -> [f32; 4] {
+        let active = voltage_limits.iter().flatten().count();
+        let share = (budget - *self.quiescent).max(0.0) * *self.efficiency / active.max(1) as f32;
+        voltage_limits.map(|limit| {
+            limit
+                .filter(|_| budget.is_finite())
+                .map_or(f32::INFINITY, |limit| share / limit)
+        })
+    }
+
+    /// Check the supply rails.
+    ///
+    /// # Args
+    /// * `p3v3` - 3.3 V rail voltage
+    /// * `p5v` - 5 V rail voltage
+    /// * `p12v` - 12 V rail voltage
+    ///
+    /// # Returns
+    /// True if any rail is below its threshold.
+    pub fn undervoltage(&self, p3v3: f32, p5v: f32, p12v: f32) -> bool {
+        p3v3 < *self.p3v3 || p5v < *self.p5v || p12v < *self.p12v
+    }
+
+    /// Update the power budget throttle.
+    ///
+    /// The output power scales approximately with the square of the output current.
+    ///
+    /// # Args
+    /// * `state` - Throttle state
+    /// * `budget` - Input power budget in Watt
+    /// * `power` - Input power in Watt
+    ///
+    /// # Returns
+    /// The output current scale factor in `(0, 1]`, rounded down to steps of 1 %.
+    pub fn throttle(&self, state: &mut Throttle, budget: f32, power: f32) -> f32 {
+        if !budget.is_finite() {
+            state.scale = 1.0;
+        } else if power > budget {
+            state.scale *= Self::MARGIN * (budget / power).sqrt();
+        } else if power < Self::MARGIN * budget {
+            state.scale = (state.scale * Self::RECOVERY).min(1.0);
+        }
+        // Avoid a stuck zero scale.
+        state.scale = state.scale.max(1e-2);
+        (state.scale * 100.0).floor() / 100.0
+    }
+}
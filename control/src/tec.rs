@@ -0,0 +1,132 @@
+//! # Thermostat_EEM TEC driver constants and DAC code conversion
+//!
+//! The output current is set by a DAC driving the CTLI input of the MAX1968 TEC driver.
+//! TEC driver datasheet: `<https://datasheets.maximintegrated.com/en/ds/MAX1968-MAX1969.pdf>`
+
+use miniconf::{Leaf, Tree};
+
+// DAC and PWM shared constants
+pub const R_SENSE: f32 = 0.05; // TEC current sense resistor
+pub const VREF_TEC: f32 = 1.5; // TEC driver reference voltage
+
+pub const MAX_CURRENT_LIMIT: f32 = 3.0; // As per MAX1968 datasheet. (Pwm::V_PWM * 0.15) / (VREF_TEC * R_SENSE) for 100% duty cycle equivalent.
+pub const MAX_VOLTAGE_LIMIT: f32 = 4.3; // As per MAX1968 datasheet
+
+/// DAC conversion error.
+#[derive(Copy, Clone, Debug)]
+pub enum Error {
+    /// The current is out of bounds. Contains the code saturated to the valid range.
+    Bounds(DacCode),
+    /// The current is NaN.
+    Nan,
+}
+
+/// A type representing a DAC sample.
+#[derive(Copy, Clone, Debug)]
+pub struct DacCode(u32);
+impl DacCode {
+    // DAC constants
+    const MAX_DAC_WORD: i32 = 1 << 20; // maximum DAC dataword (exclusive) plus 2 bit due to interface alignment
+    const VREF_DAC: f32 = 3.0; // DAC reference voltage
+    pub const MAX_CURRENT: f32 =
+        ((DacCode::MAX_DAC_WORD - 1) as f32 / DacCode::MAX_DAC_WORD as f32 * DacCode::VREF_DAC
+            - VREF_TEC)
+            / (10.0 * R_SENSE);
+    /// Zero output current.
+    pub const ZERO: DacCode =
+        DacCode((VREF_TEC * (DacCode::MAX_DAC_WORD as f32 / DacCode::VREF_DAC)) as u32);
+}
+
+impl TryFrom<f32> for DacCode {
+    type Error = Error;
+    /// Convert an f32 representing a current int the corresponding DAC output code.
+    ///
+    /// Out of bounds currents are saturated to the valid range and returned with
+    /// [Error::Bounds].
+    fn try_from(current: f32) -> Result<DacCode, Error> {
+        if current.is_nan() {
+            return Err(Error::Nan);
+        }
+        // Current to DAC word conversion
+        let ctli_voltage = current * (10.0 * R_SENSE) + VREF_TEC;
+        // Note: float to int casts saturate.
+        let dac_code = (ctli_voltage * (DacCode::MAX_DAC_WORD as f32 / DacCode::VREF_DAC)) as i32;
+
+        if !(0..DacCode::MAX_DAC_WORD).contains(&dac_code) {
+            let saturated = dac_code.clamp(0, DacCode::MAX_DAC_WORD - 1);
+            return Err(Error::Bounds(Self(saturated as u32)));
+        };
+
+        Ok(Self(dac_code as u32))
+    }
+}
+
+impl DacCode {
+    /// Convert a current to the corresponding DAC output code applying the DAC path calibration.
+    ///
+    /// # Args
+    /// * `current` - Output current in Ampere
+    /// * `calibration` - DAC path calibration
+    pub fn calibrated(current: f32, calibration: &Affine) -> Result<DacCode, Error> {
+        DacCode::try_from(calibration.invert(current))
+    }
+}
+
+impl From<DacCode> for u32 {
+    fn from(code: DacCode) -> u32 {
+        code.0
+    }
+}
+
+/// Affine calibration of a current path.
+///
+/// `actual = gain * nominal + offset`
+#[derive(Copy, Clone, Debug, PartialEq, Tree)]
+pub struct Affine {
+    /// Units: A
+    pub offset: Leaf<f32>,
+    /// Units: A/A
+    pub gain: Leaf<f32>,
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Self {
+            offset: 0.0.into(),
+            gain: 1.0.into(),
+        }
+    }
+}
+
+impl Affine {
+    /// Actual value from a nominal value.
+    pub fn apply(&self, nominal: f32) -> f32 {
+        *self.gain * nominal + *self.offset
+    }
+
+    /// Nominal value from an actual value.
+    pub fn invert(&self, actual: f32) -> f32 {
+        (actual - *self.offset) / *self.gain
+    }
+
+    /// Least squares fit of `actual` against `nominal`.
+    ///
+    /// # Returns
+    /// The calibration or `None` if the nominal values do not span a range or the gain
+    /// is not positive and finite.
+    pub fn fit(nominal: &[f32], actual: &[f32]) -> Option<Self> {
+        let n = nominal.len().min(actual.len()) as f32;
+        let mean = |v: &[f32]| v.iter().sum::<f32>() / n;
+        let (mx, my) = (mean(nominal), mean(actual));
+        let (mut sxx, mut sxy) = (0.0, 0.0);
+        for (x, y) in nominal.iter().zip(actual.iter()) {
+            sxx += (x - mx) * (x - mx);
+            sxy += (x - mx) * (y - my);
+        }
+        let gain = sxy / sxx;
+        (gain.is_finite() && gain > 0.0).then(|| Self {
+            offset: (my - gain * mx).into(),
+            gain: gain.into(),
+        })
+    }
+}
@@ -0,0 +1,87 @@
+//! Alarm limit tests.
+
+use thermostat_control::alarm::{Action, Limit, LimitState, Severity};
+
+fn limit() -> Limit {
+    Limit {
+        min: 20.0.into(),
+        max: 30.0.into(),
+        hysteresis: 1.0.into(),
+        delay: 2.0.into(),
+        severity: Severity::Critical.into(),
+        action: Default::default(),
+    }
+}
+
+#[test]
+fn delay_hysteresis() {
+    let limit = limit();
+    let mut state = LimitState::default();
+    assert!(!limit.update(&mut state, [25.0; 2], 1.0, false));
+    // A short violation does not assert.
+    assert!(!limit.update(&mut state, [31.0; 2], 1.0, false));
+    assert!(!limit.update(&mut state, [25.0; 2], 1.0, false));
+    assert!(!limit.update(&mut state, [31.0; 2], 1.0, false));
+    assert!(limit.update(&mut state, [31.0; 2], 1.0, false));
+    assert_eq!(state.duration(), 2.0);
+    // Within the hysteresis
+    assert!(limit.update(&mut state, [29.5; 2], 1.0, false));
+    assert!(!limit.update(&mut state, [28.5; 2], 1.0, false));
+    assert_eq!(state.duration(), 0.0);
+    // Extremes and NaN
+    assert!(!limit.update(&mut state, [19.0, 25.0], 1.0, false));
+    assert!(limit.update(&mut state, [f32::NAN; 2], 1.0, false));
+}
+
+#[test]
+fn validate() {
+    let mut limit = limit();
+    limit.validate().unwrap();
+    *limit.hysteresis = 5.0;
+    limit.validate().unwrap();
+    // The value could never be back within the limits by the hysteresis.
+    *limit.hysteresis = 5.5;
+    assert!(limit.validate().is_err());
+    *limit.hysteresis = 1.0;
+    *limit.max = 15.0;
+    assert!(limit.validate().is_err());
+}
+
+#[test]
+fn latch() {
+    let limit = limit();
+    let mut state = LimitState::default();
+    limit.update(&mut state, [10.0; 2], 5.0, true);
+    assert!(limit.update(&mut state, [25.0; 2], 1.0, true));
+    state.acknowledge();
+    assert!(!limit.update(&mut state, [25.0; 2], 1.0, true));
+}
+
+#[test]
+fn violation() {
+    let limit = limit();
+    let mut state = LimitState::default();
+    limit.update(&mut state, [19.0, 25.0], 1.0, true);
+    assert_eq!(limit.violation(&state), None);
+    limit.update(&mut state, [19.0, 31.0], 1.0, true);
+    let violation = limit.violation(&state).unwrap();
+    assert_eq!((violation.value, violation.limit), (19.0, 20.0));
+    assert_eq!(violation.duration, 2.0);
+    assert_eq!(violation.severity, Severity::Critical);
+    // Latched after the violation ended
+    limit.update(&mut state, [25.0, 32.0], 1.0, true);
+    assert_eq!(limit.violation(&state).unwrap().limit, 30.0);
+    limit.update(&mut state, [25.0; 2], 1.0, true);
+    assert_eq!(limit.violation(&state).unwrap().duration, 0.0);
+}
+
+#[test]
+fn action_precedence() {
+    let mut actions = [Action::Hold, Action::None, Action::Off, Action::Flash];
+    actions.sort();
+    assert_eq!(
+        actions,
+        [Action::None, Action::Flash, Action::Hold, Action::Off]
+    );
+    assert!(Action::Setpoint < Action::Hold);
+}
@@ -0,0 +1,75 @@
+//! Output current calibration tests.
+
+use thermostat_control::{
+    output_channel::{Actuator, OutputChannel, State},
+    tec::{Affine, DacCode, Error},
+};
+
+#[test]
+fn fit() {
+    let nominal = [-1.0, -0.5, 0.0, 0.5, 1.0];
+    let actual = nominal.map(|x| 1.02 * x - 0.03);
+    let cal = Affine::fit(&nominal, &actual).unwrap();
+    assert!((*cal.gain - 1.02).abs() < 1e-5);
+    assert!((*cal.offset + 0.03).abs() < 1e-5);
+    for x in nominal {
+        assert!((cal.invert(cal.apply(x)) - x).abs() < 1e-6);
+    }
+    // Degenerate
+    assert!(Affine::fit(&[0.5; 3], &[0.1, 0.2, 0.3]).is_none());
+    // Reversed polarity
+    assert!(Affine::fit(&nominal, &nominal.map(|x| -x)).is_none());
+}
+
+#[test]
+fn dac_code() {
+    let cal = Affine {
+        offset: 0.03.into(),
+        gain: 1.02.into(),
+    };
+    // The calibrated code delivers the requested current.
+    let code = u32::from(DacCode::calibrated(1.0, &cal).unwrap());
+    let nominal = u32::from(DacCode::try_from(cal.invert(1.0)).unwrap());
+    assert_eq!(code, nominal);
+    assert!(code < u32::from(DacCode::try_from(1.0).unwrap()));
+}
+
+#[test]
+fn saturation() {
+    assert_eq!(
+        u32::from(DacCode::try_from(0.0).unwrap()),
+        u32::from(DacCode::ZERO)
+    );
+    // Out of range currents saturate.
+    let Err(Error::Bounds(max)) = DacCode::try_from(10.0) else {
+        panic!()
+    };
+    assert!(u32::from(max) > u32::from(DacCode::try_from(2.99).unwrap()));
+    let Err(Error::Bounds(min)) = DacCode::try_from(f32::NEG_INFINITY) else {
+        panic!()
+    };
+    assert_eq!(u32::from(min), 0);
+    assert!(matches!(DacCode::try_from(f32::NAN), Err(Error::Nan)));
+}
+
+#[test]
+fn currents() {
+    let mut output = OutputChannel::default();
+    *output.state = State::Calibrate;
+    *output.calibration.load = 2.0;
+    *output.calibration.current = 3.0;
+    *output.voltage_limit = 4.0;
+    output.validate().unwrap();
+    // Limited by the voltage across the load.
+    let currents = output.calibration_currents::<5>();
+    assert!((currents[4] - 0.95 * 4.0 / 2.0).abs() < 1e-6);
+    assert_eq!(currents[0], -currents[4]);
+    assert_eq!(currents[2], 0.0);
+    let [max, min] = output.current_limits();
+    assert!(max > currents[4] && min < currents[0]);
+
+    *output.actuator = Actuator::Heater;
+    let currents = output.calibration_currents::<3>();
+    assert_eq!(currents[0], 0.0);
+    assert_eq!(output.current_limits()[1], 0.0);
+}
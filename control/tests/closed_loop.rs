@@ -0,0 +1,249 @@
+//! Closed loop tests of the output channel control path against thermal plant models.
+
+use thermostat_control::{
+    output_channel::{Actuator, OutputChannel, RunawayState, State},
+    plant::{Fopdt, Peltier},
+};
+
+/// Output channel with a PI controller on the first input.
+fn channel(kp: f32, ki: f32, setpoint: f32, limit: f32) -> OutputChannel {
+    let mut output = OutputChannel::default();
+    *output.state = State::On;
+    output.weights[0][0] = 1.0;
+    *output.pid.kp = kp;
+    *output.pid.ki = ki;
+    *output.pid.setpoint = setpoint;
+    *output.pid.min = -limit;
+    *output.pid.max = limit;
+    output.validate().unwrap();
+    output
+}
+
+/// Closed loop of an output channel and a plant.
+struct Loop<P> {
+    output: OutputChannel,
+    iir_state: [f64; 4],
+    plant: P,
+    update: fn(&mut P, f64, f64) -> f64,
+    temperature: f64,
+    current: f32,
+}
+
+impl<P> Loop<P> {
+    /// The plant starts at `temperature` with zero current.
+    fn new(
+        output: OutputChannel,
+        plant: P,
+        update: fn(&mut P, f64, f64) -> f64,
+        temperature: f64,
+    ) -> Self {
+        Self {
+            output,
+            iir_state: Default::default(),
+            plant,
+            update,
+            temperature,
+            current: 0.0,
+        }
+    }
+
+    /// Run for `duration` seconds. Calls `check(time, loop)` after every update.
+    fn run(&mut self, duration: f32, mut check: impl FnMut(f32, &Self)) {
+        let period = self.output.pid.period;
+        for n in 0..(duration / period) as usize {
+            let mut temperatures = [[0.0; 4]; 4];
+            temperatures[0][0] = self.temperature;
+            let input = self.output.input(&temperatures);
+            let [min, max] = self.output.current_range();
+            self.current = (self.output.update(input, &mut self.iir_state) as f32)
+                .max(min)
+                .min(max);
+            self.temperature = (self.update)(&mut self.plant, self.current as _, period as _);
+            check(n as f32 * period, self);
+        }
+    }
+}
+
+/// A plant with a 10 s time constant, -10 K/A gain and 0.5 s dead time.
+fn fopdt() -> Fopdt<503> {
+    Fopdt::new(-10.0, 10.0, 25.0)
+}
+
+/// SIMC tuning for [fopdt].
+const KP: f32 = 1.0;
+const KI: f32 = 0.25;
+
+#[test]
+fn step_response() {
+    let mut l = Loop::new(channel(KP, KI, 20.0, 3.0), fopdt(), Fopdt::update, 25.0);
+    let [_, max] = l.output.current_range();
+    let mut saturated = false;
+    l.run(60.0, |t, l| {
+        // Large initial error saturates the output.
+        saturated |= l.current == max;
+        // Negligible overshoot
+        assert!(l.temperature > 19.95, "overshoot at {t}: {}", l.temperature);
+        if t > 25.0 {
+            assert!((l.temperature - 20.0).abs() < 0.05, "not settled at {t}");
+        }
+    });
+    assert!(saturated);
+    assert!((l.temperature - 20.0).abs() < 1e-3);
+    // Steady state current: (25 - 20) K / 10 K/A
+    assert!((l.current - 0.5).abs() < 1e-3);
+}
+
+#[test]
+fn setpoint_ramp() {
+    let mut l = Loop::new(channel(KP, KI, 25.0, 3.0), fopdt(), Fopdt::update, 25.0);
+    // Ramp the setpoint down by 5 K at 0.1 K/s.
+    let rate = 0.1;
+    let period = l.output.pid.period;
+    let steps = (5.0 / (rate * period)).round() as usize;
+    for n in 1..=steps {
+        *l.output.pid.setpoint = 25.0 - rate * period * n as f32;
+        l.output.validate().unwrap();
+        l.run(period, |_, l| {
+            let error = l.temperature - *l.output.pid.setpoint as f64;
+            if n as f32 * period > 30.0 {
+                // The PI loop follows a ramp with a constant lag of rate / (KI |gain|) and
+                // rate KP / KI as the setpoint enters through the integrator only.
+                let lag = (rate / (KI * 10.0) + rate * KP / KI) as f64;
+                assert!((error - lag).abs() < 0.005, "ramp error {error}");
+            }
+        });
+    }
+    l.run(30.0, |_, _| {});
+    assert!((l.temperature - 20.0).abs() < 1e-3);
+}
+
+#[test]
+fn saturation() {
+    // The setpoint is out of reach with the current limit.
+    let mut l = Loop::new(channel(KP, KI, -15.0, 3.0), fopdt(), Fopdt::update, 25.0);
+    let [_, max] = l.output.current_range();
+    l.run(60.0, |t, l| {
+        if t > 1.0 {
+            assert_eq!(l.current, max);
+        }
+    });
+    // Close to the steady state at the current limit: 25 K - 3 A * 10 K/A
+    assert!((l.temperature + 5.0).abs() < 0.1);
+    // The PWM current limit leaves headroom above the output current.
+    assert!(l.output.current_limits()[0] > max);
+
+    // No integrator windup: recovers as fast as from an unsaturated step.
+    *l.output.pid.setpoint = 5.0;
+    l.output.validate().unwrap();
+    l.run(30.0, |t, l| {
+        if t > 1.0 {
+            assert!(l.current < max);
+        }
+        if t > 25.0 {
+            assert!((l.temperature - 5.0).abs() < 0.05, "not settled at {t}");
+        }
+    });
+}
+
+#[test]
+fn current_limit() {
+    // Output limits exceeding the maximum current are clamped.
+    let l = channel(KP, KI, 0.0, 10.0);
+    let [min, max] = l.current_range();
+    assert!(max <= 3.0 && max > 2.99);
+    assert_eq!(min, -max);
+}
+
+#[test]
+fn peltier() {
+    let mut l = Loop::new(
+        channel(1.0, 0.1, 15.0, 2.0),
+        Peltier::default(),
+        Peltier::update,
+        25.0,
+    );
+    l.run(150.0, |t, l| {
+        assert!(l.temperature > 14.9, "overshoot at {t}: {}", l.temperature);
+        if t > 100.0 {
+            assert!((l.temperature - 15.0).abs() < 0.05, "not settled at {t}");
+        }
+    });
+    // Cooling current, heat is dumped into the heat sink.
+    assert!(l.current > 0.0);
+    assert!(l.plant.sink > l.plant.ambient);
+    assert!(l.plant.voltage(l.current as _) > 0.0);
+}
+
+/// Run a loop with runaway detection (5 s, 1 K) and return the time of the first detection.
+fn runaway<P>(l: &mut Loop<P>, duration: f32) -> Option<f32> {
+    *l.output.runaway.time = 5.0;
+    *l.output.runaway.margin = 1.0;
+    let mut state = RunawayState::default();
+    let mut detected = None;
+    let output = l.output;
+    l.run(duration, |t, l| {
+        if output.runaway(&mut state, l.temperature, l.current) {
+            detected.get_or_insert(t);
+        }
+    });
+    detected
+}
+
+#[test]
+fn runaway_polarity() {
+    // Correct polarity, also while saturated
+    let mut l = Loop::new(channel(KP, KI, 20.0, 3.0), fopdt(), Fopdt::update, 25.0);
+    assert_eq!(runaway(&mut l, 60.0), None);
+    let mut l = Loop::new(channel(KP, KI, -15.0, 3.0), fopdt(), Fopdt::update, 25.0);
+    assert_eq!(runaway(&mut l, 60.0), None);
+
+    // Reversed polarity
+    let mut l = Loop::new(
+        channel(KP, KI, 20.0, 3.0),
+        Fopdt::<503>::new(10.0, 10.0, 25.0),
+        Fopdt::update,
+        25.0,
+    );
+    assert!(runaway(&mut l, 60.0).is_some_and(|t| t < 10.0));
+}
+
+#[test]
+fn runaway_heater_idle() {
+    // A heater idling at zero current while the ambient drifts above the setpoint.
+    let mut output = channel(-1.0, -0.1, 20.0, 5.0);
+    *output.actuator = Actuator::Heater;
+    *output.pid.min = 0.0;
+    output.validate().unwrap();
+    let mut plant = Fopdt::<503>::new(10.0, 10.0, 25.0);
+    plant.temperature = 20.0;
+    let mut l = Loop::new(output, plant, Fopdt::update, 20.0);
+    assert_eq!(runaway(&mut l, 60.0), None);
+    assert_eq!(l.current, 0.0);
+    assert!(l.temperature > 24.0);
+}
+
+#[test]
+fn runaway_validate() {
+    let mut output = channel(KP, KI, 20.0, 3.0);
+    *output.runaway.time = f32::NAN;
+    assert!(output.validate().is_err());
+    *output.runaway.time = 5.0;
+    *output.runaway.margin = -1.0;
+    assert!(output.validate().is_err());
+    *output.runaway.margin = 1.0;
+    output.validate().unwrap();
+}
+
+#[test]
+fn mismatch_validate() {
+    let mut output = channel(KP, KI, 20.0, 3.0);
+    *output.mismatch.tolerance = 0.0;
+    assert!(output.validate().is_err());
+    *output.mismatch.tolerance = f32::NAN;
+    assert!(output.validate().is_err());
+    *output.mismatch.tolerance = 0.1;
+    *output.mismatch.time = f32::NAN;
+    assert!(output.validate().is_err());
+    *output.mismatch.time = f32::INFINITY;
+    output.validate().unwrap();
+}
@@ -0,0 +1,30 @@
+//! Board temperature derating tests.
+
+use thermostat_control::{derating::Derating, output_channel::OutputChannel};
+
+#[test]
+fn factor() {
+    let derating = Derating::default();
+    assert_eq!(derating.factor(None), 1.0);
+    assert_eq!(derating.factor(Some(f32::NAN)), 1.0);
+    assert_eq!(derating.factor(Some(25.0)), 1.0);
+    assert_eq!(derating.factor(Some(*derating.start)), 1.0);
+    let mid = derating.factor(Some(0.5 * (*derating.start + *derating.end)));
+    assert!((mid - 0.5).abs() < 0.011);
+    assert_eq!(derating.factor(Some(*derating.end)), *derating.min);
+    assert_eq!(derating.factor(Some(150.0)), *derating.min);
+}
+
+#[test]
+fn current_range() {
+    let mut output = OutputChannel::default();
+    *output.pid.min = -2.0;
+    *output.pid.max = 2.0;
+    output.validate().unwrap();
+    let [min, max] = output.current_range();
+    let [pos, neg] = output.current_limits();
+    output.derating = 0.5;
+    assert_eq!(output.current_range(), [0.5 * min, 0.5 * max]);
+    let [pos_derated, neg_derated] = output.current_limits();
+    assert!(pos_derated < pos && neg_derated > neg);
+}
@@ -0,0 +1,47 @@
+//! Fan control tests.
+
+use thermostat_control::fan::{FanControl, Mode, StallState};
+
+#[test]
+fn output() {
+    let mut fan = FanControl::default();
+    fan.validate().unwrap();
+    // Cold and idle
+    assert_eq!(fan.output(Some(25.0), 0.0), 0.0);
+    // Temperature ramp
+    assert_eq!(fan.output(Some(60.0), 0.0), *fan.max);
+    let half = fan.output(Some(47.5), 0.0);
+    assert!((half - 0.5 * (*fan.min + *fan.max)).abs() < 1e-6);
+    // The larger demand wins.
+    assert_eq!(fan.output(Some(25.0), 100.0), *fan.max);
+    assert_eq!(fan.output(None, 11.0), half);
+    // Invalid temperature readings are ignored.
+    assert_eq!(fan.output(Some(f32::NAN), 0.0), 0.0);
+
+    *fan.mode = Mode::Fixed;
+    assert_eq!(fan.output(Some(100.0), 100.0), *fan.duty);
+    *fan.mode = Mode::Off;
+    assert_eq!(fan.output(Some(100.0), 100.0), 0.0);
+
+    *fan.min = 0.9;
+    *fan.max = 0.5;
+    assert!(fan.validate().is_err());
+}
+
+#[test]
+fn stall() {
+    let fan = FanControl::default();
+    let mut state = StallState::default();
+    // Not driven
+    for _ in 0..10 {
+        assert!(!fan.stalled(&mut state, 0.0, 0.0, 1.0));
+    }
+    // Spinning
+    for _ in 0..10 {
+        assert!(!fan.stalled(&mut state, 0.5, 2000.0, 1.0));
+    }
+    let stalled: Vec<_> = (0..10)
+        .map(|_| fan.stalled(&mut state, 0.5, 0.0, 1.0))
+        .collect();
+    assert_eq!(stalled.iter().position(|s| *s), Some(5));
+}
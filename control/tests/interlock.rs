@@ -0,0 +1,33 @@
+//! Output interlock tests.
+
+use thermostat_control::output_channel::OutputChannel;
+
+#[test]
+fn limits() {
+    let mut output = OutputChannel::default();
+    let mut temperatures = [[25.0; 4]; 4];
+    let mut enabled = [[true; 4]; 4];
+    // No limits
+    assert!(!output.interlock(&temperatures, &enabled));
+    temperatures[1][2] = 1e3;
+    assert!(!output.interlock(&temperatures, &enabled));
+
+    let limits = &mut output.interlock[0][1];
+    (*limits.min, *limits.max) = (10.0, 40.0);
+    assert!(!output.interlock(&temperatures, &enabled));
+    *output.interlock[0][1].enable = true;
+    assert!(!output.interlock(&temperatures, &enabled));
+    temperatures[0][1] = 40.5;
+    assert!(output.interlock(&temperatures, &enabled));
+    temperatures[0][1] = 9.5;
+    assert!(output.interlock(&temperatures, &enabled));
+    temperatures[0][1] = f64::NAN;
+    assert!(output.interlock(&temperatures, &enabled));
+
+    // Disabled inputs
+    temperatures[0][1] = 25.0;
+    enabled[1][2] = false;
+    assert!(!output.interlock(&temperatures, &enabled));
+    enabled[0][1] = false;
+    assert!(output.interlock(&temperatures, &enabled));
+}
@@ -0,0 +1,24 @@
+//! Rate of change estimation tests.
+
+use thermostat_control::rate::Rate;
+
+#[test]
+fn ramp() {
+    let mut rate = Rate::default();
+    let mut r = None;
+    // 2 K/s ramp sampled at 3 ms
+    for i in 0..2000 {
+        let t = i * 3;
+        r = rate.update(20.0 + 2e-3 * t as f64, t);
+    }
+    assert!((r.unwrap() - 2.0).abs() < 0.05);
+
+    // Constant
+    for i in 2000..6000 {
+        r = rate.update(32.0, i * 3);
+    }
+    assert!(r.unwrap().abs() < 0.01);
+
+    assert_eq!(rate.update(f64::NAN, 18_000), None);
+    assert_eq!(rate.update(20.0, 18_003), None);
+}
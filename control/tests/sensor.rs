@@ -0,0 +1,47 @@
+//! Sensor conversion tests.
+
+use thermostat_control::sensor::{AdcCode, Convert, Dt670, Linear, Ntc, Sensor};
+
+/// ADC code for a voltage relative to the reference.
+fn code(relative: f32) -> AdcCode {
+    let lsb = f32::from(AdcCode::from(1));
+    AdcCode::from((relative / lsb).round() as u32)
+}
+
+#[test]
+fn linear() {
+    // E.g. a PT100 RTD in a bridge: 0 °C at zero, 260 K per unit relative voltage.
+    let rtd = Linear::new(0.0, 260.0);
+    assert!(rtd.convert(code(0.0)).abs() < 1e-6);
+    assert!((rtd.convert(code(0.25)) - 65.0).abs() < 1e-3);
+    assert!((Linear::default().convert(code(0.5)) - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn ntc() {
+    let (t0, r0, r_ref, beta) = (25.0, 10.0e3, 5.0e3, 3988.0);
+    let ntc = Ntc::new(t0, r0, r_ref, beta);
+    for t in [-20.0, 0.0, 25.0, 50.0, 100.0] {
+        // Beta equation and the resistive divider
+        let r = r0 * (beta * (1.0 / (t + 273.15) - 1.0 / (t0 + 273.15))).exp();
+        let relative = r / (r + r_ref);
+        let converted = Sensor::Ntc(ntc).convert(code(relative as _));
+        assert!((converted - f64::from(t)).abs() < 1e-3, "{t}: {converted}");
+    }
+}
+
+#[test]
+fn dt670() {
+    let dt670 = Dt670::default();
+    // Relative to the 2.5 V default reference
+    let convert = |voltage: f32| dt670.convert(code(voltage / 2.5));
+    // Curve points in Kelvin
+    for (t, voltage) in [(4.2, 1.57848), (77.35, 1.027594), (300.0, 0.559639)] {
+        let converted = convert(voltage);
+        assert!((converted - t).abs() < 0.1, "{t}: {converted}");
+    }
+    // Between points
+    assert!((convert(0.571138) - 295.0).abs() < 0.01);
+    // Monotonic
+    assert!(convert(0.9) > convert(1.0));
+}
@@ -0,0 +1,70 @@
+//! Supply monitoring and power budget tests.
+
+use thermostat_control::{
+    output_channel::OutputChannel,
+    supply::{Supply, Throttle},
+};
+
+#[test]
+fn undervoltage() {
+    let supply = Supply::default();
+    assert!(!supply.undervoltage(3.3, 5.0, 12.0));
+    assert!(supply.undervoltage(3.3, 5.0, 9.0));
+    assert!(supply.undervoltage(2.5, 5.0, 12.0));
+}
+
+#[test]
+fn throttle() {
+    let supply = Supply::default();
+    let mut state = Throttle::default();
+    // Unlimited
+    assert_eq!(supply.throttle(&mut state, f32::INFINITY, 100.0), 1.0);
+
+    // Resistive load with 2 W baseline and 20 W at full scale.
+    let budget = *supply.poe_af;
+    let power = |scale: f32| 2.0 + 20.0 * scale * scale;
+    let mut scale = 1.0;
+    for _ in 0..20 {
+        scale = supply.throttle(&mut state, budget, power(scale));
+    }
+    assert!(scale < 1.0);
+    // Within budget after settling
+    for _ in 0..100 {
+        scale = supply.throttle(&mut state, budget, power(scale));
+        assert!(power(scale) <= budget * 1.1);
+        // Rounded to 1 %
+        assert_eq!((scale * 100.0).round() / 100.0, scale);
+    }
+    assert!(power(scale) > 0.7 * budget);
+
+    // Recovers without load
+    for _ in 0..100 {
+        scale = supply.throttle(&mut state, budget, 2.0);
+    }
+    assert_eq!(scale, 1.0);
+}
+
+#[test]
+fn current_cap() {
+    let supply = Supply::default();
+    // Unlimited
+    assert_eq!(
+        supply.current_cap(f32::INFINITY, [Some(4.0); 4]),
+        [f32::INFINITY; 4]
+    );
+
+    let budget = *supply.poe_af;
+    let cap = supply.current_cap(budget, [Some(4.0), None, Some(2.0), None]);
+    assert_eq!(cap[1], f32::INFINITY);
+    // Worst case input power at the voltage limits within the budget
+    let power = *supply.quiescent + (4.0 * cap[0] + 2.0 * cap[2]) / *supply.efficiency;
+    assert!((power - budget).abs() < 1e-4);
+
+    // Applied to the output current range
+    let mut output = OutputChannel::default();
+    *output.pid.min = -3.0;
+    *output.pid.max = 3.0;
+    output.validate().unwrap();
+    output.current_cap = cap[0];
+    assert_eq!(output.current_range(), [-cap[0], cap[0]]);
+}
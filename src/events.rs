@@ -0,0 +1,223 @@
+//! # Thermostat_EEM persistent event log
+//!
+//! Events are recorded from any context into a RAM queue together with the uptime.
+//! The `usb` task, which owns the flash, appends them to a ring buffer in flash one record per
+//! poll. Settings changes are coalesced to limit the flash writes from bursts. The oldest
+//! records are overwritten when the ring buffer is full. It is only cleared on an explicit
+//! command.
+//!
+//! The log is readable with the `events` USB serial command and by publishing to the
+//! `<prefix>/events/request` MQTT topic. The most recent records are published as a JSON array on
+//! `<prefix>/events/response`. A request with the payload `clear` clears the log.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use embassy_futures::block_on;
+use heapless::{mpmc::Q16, String};
+use rtic_monotonics::Monotonic;
+use sequential_storage::{cache::NoCache, queue, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hardware::{flash::Flash, Systick},
+    output_channel::Fault,
+};
+
+/// Logged event.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Event {
+    /// Device boot. `watchdog` is set if the reset was caused by the watchdog.
+    Boot { watchdog: bool },
+    /// A panic message was retained from the previous boot.
+    Panic { message: String<64> },
+    /// Hardware overtemperature interlock trip.
+    Overtemp,
+    /// Output channel fault.
+    Fault { output: u8, fault: Fault },
+    /// ADC conversion error on an input channel.
+    Adc { adc: u8, channel: u8 },
+    /// ADC data lost due to a processing queue overflow.
+    Overflow,
+    /// Settings changed via MQTT or USB.
+    Settings,
+}
+
+/// Event log record.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Record {
+    /// Time since boot in seconds.
+    pub uptime: u32,
+    /// The event.
+    pub event: Event,
+}
+
+/// Request to the event log.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Request {
+    /// Publish the most recent records.
+    Read,
+    /// Clear the log.
+    Clear,
+}
+
+/// Records not yet written to flash.
+static QUEUE: Q16<Record> = Q16::new();
+
+/// Minimum interval between [Event::Settings] records in seconds.
+const SETTINGS_HOLDOFF: u32 = 10;
+
+/// No settings change pending or none recorded yet.
+const NONE: u32 = u32::MAX;
+
+/// Uptime of the first settings change not yet recorded.
+static SETTINGS_PENDING: AtomicU32 = AtomicU32::new(NONE);
+
+/// Uptime of the last [Event::Settings] record taken.
+static SETTINGS_LAST: AtomicU32 = AtomicU32::new(NONE);
+
+fn uptime() -> u32 {
+    Systick::now().duration_since_epoch().to_secs()
+}
+
+/// Record an event.
+///
+/// The event is dropped if the queue is full. Settings changes are coalesced into at most one
+/// record every [SETTINGS_HOLDOFF] seconds.
+pub fn record(event: Event) {
+    let uptime = uptime();
+    if let Event::Settings = event {
+        SETTINGS_PENDING
+            .compare_exchange(NONE, uptime, Ordering::Relaxed, Ordering::Relaxed)
+            .ok();
+        return;
+    }
+    if QUEUE.enqueue(Record { uptime, event }).is_err() {
+        log::warn!("Event queue overflow");
+    }
+}
+
+/// Take the oldest record not yet written to flash.
+pub fn pop() -> Option<Record> {
+    QUEUE.dequeue().or_else(|| {
+        let now = uptime();
+        let last = SETTINGS_LAST.load(Ordering::Relaxed);
+        if last != NONE && now < last + SETTINGS_HOLDOFF {
+            return None;
+        }
+        let uptime = SETTINGS_PENDING.swap(NONE, Ordering::Relaxed);
+        (uptime != NONE).then(|| {
+            SETTINGS_LAST.store(now, Ordering::Relaxed);
+            Record {
+                uptime,
+                event: Event::Settings,
+            }
+        })
+    })
+}
+
+/// Append a record to the event log in flash.
+///
+/// The oldest records are overwritten if the log is full.
+pub fn append(storage: &mut Flash, record: &Record) {
+    let mut buffer = [0u8; 128];
+    let data = match postcard::to_slice(record, &mut buffer) {
+        Ok(data) => data,
+        Err(e) => {
+            log::warn!("Failed to serialize event: {e:?}");
+            return;
+        }
+    };
+    let range = storage.event_range();
+    let mut result = block_on(queue::push(
+        storage,
+        range.clone(),
+        &mut NoCache::new(),
+        data,
+        true,
+    ));
+    if let Err(Error::Corrupted { .. }) = result {
+        log::warn!("Event log corrupted, erasing");
+        clear(storage);
+        result = block_on(queue::push(storage, range, &mut NoCache::new(), data, true));
+    }
+    if let Err(e) = result {
+        log::warn!("Failed to log event: {e:?}");
+    }
+}
+
+/// Whether the event log range is erased or holds an event log.
+///
+/// The event log sectors were part of the settings storage in earlier firmware. Settings map
+/// items share the queue item format but do not deserialize as a [Record].
+pub fn valid(storage: &mut Flash) -> bool {
+    let mut buffer = [0u8; 128];
+    let range = storage.event_range();
+    match block_on(queue::peek(
+        storage,
+        range,
+        &mut NoCache::new(),
+        &mut buffer,
+    )) {
+        Ok(None) | Err(Error::Storage { .. }) => true,
+        Ok(Some(data)) => {
+            let valid = postcard::from_bytes::<Record>(data).is_ok();
+            if !valid {
+                log::warn!("Event log in unknown format");
+            }
+            valid
+        }
+        Err(e) => {
+            log::warn!("Event log in unknown format: {e:?}");
+            false
+        }
+    }
+}
+
+/// Erase the event log if it is not in the queue format.
+///
+/// This must be called once on boot after the settings have been loaded (and migrated, see
+/// [crate::settings::SerialSettingsPlatform::load]) and before the log is used.
+pub fn check(storage: &mut Flash) {
+    if !valid(storage) {
+        log::warn!("Erasing event log");
+        clear(storage);
+    }
+}
+
+/// Read the event log from flash, oldest record first.
+///
+/// # Args
+/// * `storage` - The flash
+/// * `f` - Called for each record
+pub fn read(storage: &mut Flash, mut f: impl FnMut(Record)) {
+    let mut buffer = [0u8; 128];
+    let range = storage.event_range();
+    let mut cache = NoCache::new();
+    let mut iter = match block_on(queue::iter(storage, range, &mut cache)) {
+        Ok(iter) => iter,
+        Err(e) => {
+            log::warn!("Failed to read event log: {e:?}");
+            return;
+        }
+    };
+    loop {
+        match block_on(iter.next(&mut buffer)) {
+            Ok(Some(entry)) => match postcard::from_bytes(&entry) {
+                Ok(record) => f(record),
+                Err(e) => log::warn!("Failed to deserialize event: {e:?}"),
+            },
+            Ok(None) => break,
+            Err(e) => {
+                log::warn!("Failed to read event log: {e:?}");
+                break;
+            }
+        }
+    }
+}
+
+/// Erase the event log in flash.
+pub fn clear(storage: &mut Flash) {
+    let range = storage.event_range();
+    if let Err(e) = block_on(sequential_storage::erase_all(storage, range)) {
+        log::warn!("Failed to clear event log: {e:?}");
+    }
+}
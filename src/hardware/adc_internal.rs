@@ -10,6 +10,7 @@ use super::{
     dac::{R_SENSE, VREF_TEC},
     OutputChannelIdx,
 };
+use thermostat_control::tec::Affine;
 
 const V_REF: f32 = 3.0; // ADC reference voltage
 
@@ -66,10 +67,15 @@ impl AdcInternal {
         AdcInternal { adc1, adc3, pins }
     }
 
-    pub fn read(&mut self, ch: AdcChannel) -> f32 {
+    /// Read a channel.
+    ///
+    /// # Args
+    /// * `ch` - The channel
+    /// * `sense` - Current sense path calibration of each output channel
+    pub fn read(&mut self, ch: AdcChannel, sense: &[Affine; 4]) -> f32 {
         match ch {
             AdcChannel::OutputVoltage(ch) => self.read_output_voltage(ch),
-            AdcChannel::OutputCurrent(ch) => self.read_output_current(ch),
+            AdcChannel::OutputCurrent(ch) => self.read_output_current(ch, &sense[ch as usize]),
             AdcChannel::OutputVref(ch) => self.read_output_vref(ch),
             AdcChannel::Supply(ch) => self.read_supply(ch),
         }
@@ -89,7 +95,12 @@ impl AdcInternal {
         (code as f32 / self.adc1.slope() as f32 + OFFSET) * SCALE
     }
 
-    pub fn read_output_current(&mut self, ch: OutputChannelIdx) -> f32 {
+    /// Read the output current in Ampere applying the current sense calibration.
+    ///
+    /// # Args
+    /// * `ch` - Thermostat output channel
+    /// * `calibration` - Current sense path calibration
+    pub fn read_output_current(&mut self, ch: OutputChannelIdx, calibration: &Affine) -> f32 {
         let p = &mut self.pins.output_current;
         let code: u32 = match ch {
             OutputChannelIdx::Zero => self.adc1.read(&mut p.0),
@@ -100,7 +111,7 @@ impl AdcInternal {
         .unwrap();
         const SCALE: f32 = V_REF / R_SENSE / 8.0; // MAX1968 ITEC scale
         const OFFSET: f32 = -VREF_TEC / V_REF; // MAX1968 ITEC offset
-        (code as f32 / self.adc1.slope() as f32 + OFFSET) * SCALE
+        calibration.invert((code as f32 / self.adc1.slope() as f32 + OFFSET) * SCALE)
     }
 
     pub fn read_output_vref(&mut self, ch: OutputChannelIdx) -> f32 {
@@ -26,50 +26,7 @@ use super::OutputChannelIdx;
 // Note: Up to 30MHz clock valid according to DAC datasheet. This lead to spurious RxFIFO overruns on the STM side when probing the spi clock with a scope probe.
 const SPI_CLOCK: MegaHertz = MegaHertz::MHz(8);
 
-// DAC and PWM shared constants
-pub const R_SENSE: f32 = 0.05; // TEC current sense resistor
-pub const VREF_TEC: f32 = 1.5; // TEC driver reference voltage
-
-/// DAC value out of bounds error.
-#[derive(Debug)]
-pub enum Error {
-    Bounds,
-}
-
-/// A type representing a DAC sample.
-#[derive(Copy, Clone, Debug)]
-pub struct DacCode(u32);
-impl DacCode {
-    // DAC constants
-    const MAX_DAC_WORD: i32 = 1 << 20; // maximum DAC dataword (exclusive) plus 2 bit due to interface alignment
-    const VREF_DAC: f32 = 3.0; // DAC reference voltage
-    pub const MAX_CURRENT: f32 =
-        ((DacCode::MAX_DAC_WORD - 1) as f32 / DacCode::MAX_DAC_WORD as f32 * DacCode::VREF_DAC
-            - VREF_TEC)
-            / (10.0 * R_SENSE);
-}
-
-impl TryFrom<f32> for DacCode {
-    type Error = Error;
-    /// Convert an f32 representing a current int the corresponding DAC output code.
-    fn try_from(current: f32) -> Result<DacCode, Error> {
-        // Current to DAC word conversion
-        let ctli_voltage = current * (10.0 * R_SENSE) + VREF_TEC;
-        let dac_code = (ctli_voltage * (DacCode::MAX_DAC_WORD as f32 / DacCode::VREF_DAC)) as i32;
-
-        if !(0..DacCode::MAX_DAC_WORD).contains(&dac_code) {
-            return Err(Error::Bounds);
-        };
-
-        Ok(Self(dac_code as u32))
-    }
-}
-
-impl From<DacCode> for u32 {
-    fn from(code: DacCode) -> u32 {
-        code.0
-    }
-}
+pub use thermostat_control::tec::{Affine, DacCode, Error, R_SENSE, VREF_TEC};
 
 /// DAC gpio pins.
 ///
@@ -1,10 +1,14 @@
+//! Thermostat fan driver.
+//!
+//! The fan is driven by PWM on TIM8 CH2. The tachometer output is measured by input capture
+//! on TIM2 CH3.
 use super::hal::{
     gpio::{gpiob::*, gpioc::*, Alternate},
     hal::PwmPin,
     prelude::*,
     pwm::Pwm,
     pwm::{ComplementaryDisabled, C2},
-    // rcc::ResetEnable,
+    rcc::ResetEnable,
     rcc::{rec, CoreClocks},
     stm32::{TIM2, TIM8},
     time::KiloHertz,
@@ -15,16 +19,29 @@ pub struct FanPins {
     pub pwm: PC7<Alternate<3>>,
 }
 
+#[derive(Debug)]
 pub enum Error {
     Bounds,
 }
 
 pub struct Fan {
     pwm: Pwm<TIM8, C2, ComplementaryDisabled>,
-    // tacho:
+    tacho: TIM2,
+    _tacho_pin: PB10<Alternate<1>>,
+    /// Timer count of the last tachometer edge.
+    capture: Option<u32>,
+    /// Tachometer period in timer ticks.
+    period: Option<u32>,
 }
 
 impl Fan {
+    /// Tachometer timer tick frequency in Hz.
+    const F_TACHO: u32 = 1_000_000;
+    /// Tachometer pulses per revolution.
+    const PULSES: u32 = 2;
+    /// Tachometer timeout in timer ticks. Slower fans are reported as stopped.
+    const TIMEOUT: u32 = Self::F_TACHO;
+
     pub fn new(
         clocks: &CoreClocks,
         tim_rec: (rec::Tim2, rec::Tim8),
@@ -36,24 +53,81 @@ impl Fan {
         pwm.set_duty(0);
         pwm.enable();
 
-        // const F_TACHO: Hertz = Hertz(2);
-        // let mut _tacho = tim.0.pwm(pins.tacho, F_TACHO, tim_rec.0, clocks);
-        // let tim2 = unsafe { &*TIM2::PTR };
-        // tim2.ccer.modify(|_, w| w.cc3e())
+        // Free running 32 bit counter capturing rising tachometer edges on CH3.
+        tim_rec.0.enable().reset();
+        let tacho = tim.0;
+        let prescaler = clocks.timx_ker_ck().raw() / Self::F_TACHO - 1;
+        tacho.psc.write(|w| w.psc().bits(prescaler as u16));
+        tacho.arr.write(|w| w.bits(u32::MAX));
+        // CC3 mapped to TI3, input filter N=8 at f_DTS/8.
+        tacho
+            .ccmr2_input()
+            .modify(|_, w| unsafe { w.cc3s().bits(0b01).ic3f().bits(0b1001) });
+        tacho
+            .ccer
+            .modify(|_, w| w.cc3p().clear_bit().cc3np().clear_bit().cc3e().set_bit());
+        tacho.egr.write(|w| w.ug().set_bit());
+        tacho
+            .sr
+            .modify(|_, w| w.cc3if().clear_bit().cc3of().clear_bit());
+        tacho.dier.modify(|_, w| w.cc3ie().set_bit());
+        tacho.cr1.modify(|_, w| w.cen().set_bit());
 
         Fan {
             pwm,
-            // tacho,
+            tacho,
+            _tacho_pin: pins.tacho,
+            capture: None,
+            period: None,
         }
     }
 
     pub fn set_duty(&mut self, duty: f32) -> Result<i32, Error> {
         let max = self.pwm.get_max_duty() as i32;
         let code = (duty * max as f32) as i32;
-        if !(0..max).contains(&code) {
+        if !(0..=max).contains(&code) {
             return Err(Error::Bounds);
         }
         self.pwm.set_duty(code as u16);
         Ok(code)
     }
+
+    /// Handle a tachometer capture interrupt.
+    pub fn capture(&mut self) {
+        let sr = self.tacho.sr.read();
+        if sr.cc3if().bit_is_set() {
+            // Reading the capture clears the interrupt flag.
+            let capture = self.tacho.ccr3().read().bits();
+            if sr.cc3of().bit_is_set() {
+                self.tacho.sr.modify(|_, w| w.cc3of().clear_bit());
+            }
+            if let Some(last) = self.capture.replace(capture) {
+                self.period = Some(capture.wrapping_sub(last));
+            }
+        }
+    }
+
+    /// Get the fan speed.
+    ///
+    /// # Returns
+    /// The fan speed in RPM. Zero if there was no tachometer edge within the timeout.
+    pub fn rpm(&mut self) -> f32 {
+        let now = self.tacho.cnt.read().bits();
+        match (self.capture, self.period) {
+            (Some(last), Some(period)) if now.wrapping_sub(last) < Self::TIMEOUT && period > 0 => {
+                (60 * Self::F_TACHO) as f32 / (period as f32 * Self::PULSES as f32)
+            }
+            _ => {
+                if self
+                    .capture
+                    .is_some_and(|last| now.wrapping_sub(last) >= Self::TIMEOUT)
+                {
+                    // Restart measurement to avoid counter wrap around.
+                    self.capture = None;
+                    self.period = None;
+                }
+                0.0
+            }
+        }
+    }
 }
@@ -6,8 +6,24 @@ use stm32h7xx_hal::flash::Error as FlashError;
 pub struct Flash(pub LockedFlashBank);
 
 impl Flash {
+    /// Number of erase sectors at the end of the bank reserved for the event log.
+    const EVENT_SECTORS: u32 = 2;
+
+    /// Flash range used for settings storage.
     pub fn range(&self) -> core::ops::Range<u32> {
-        0..(self.0.len() as u32)
+        0..self.event_range().start
+    }
+
+    /// Flash range used for settings storage by earlier firmware, including the event log range.
+    pub fn legacy_range(&self) -> core::ops::Range<u32> {
+        0..self.0.len() as u32
+    }
+
+    /// Flash range used for the event log.
+    pub fn event_range(&self) -> core::ops::Range<u32> {
+        let len = self.0.len() as u32;
+        let erase_size = stm32h7xx_hal::flash::UnlockedFlashBank::ERASE_SIZE as u32;
+        len - Self::EVENT_SECTORS * erase_size..len
     }
 }
 
@@ -4,7 +4,7 @@ use super::hal::{
     gpio::{gpiod::*, gpioe::*, gpiof::*, gpiog::*, ErasedPin, Input, Output, PushPull},
     hal::digital::v2::PinState,
 };
-use crate::net::serde::Serialize;
+use crate::net::serde::{Deserialize, Serialize};
 
 use super::OutputChannelIdx;
 
@@ -61,9 +61,10 @@ impl Default for PoePower {
 }
 
 /// TEC driver PWM frequency setting
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub enum TecFrequency {
     /// Low frequency (~500 kHz)
+    #[default]
     Low,
     /// High frequency (~1 MHz)
     High,
@@ -132,6 +133,17 @@ impl Gpio {
         self.shdn[ch as usize].set_state(!PinState::from(shutdown));
     }
 
+    /// Get the shutdown state of an output channel.
+    ///
+    /// # Args
+    /// * `ch` - Thermostat output channel
+    ///
+    /// # Returns
+    /// True if the TEC driver is in shutdown mode.
+    pub fn shutdown(&self, ch: OutputChannelIdx) -> bool {
+        self.shdn[ch as usize].is_set_low()
+    }
+
     pub fn set_led(&mut self, led: Led, state: State) {
         self.led[led as usize].set_state(PinState::from(state));
     }
@@ -160,6 +172,14 @@ impl Gpio {
         self.tec_freq.set_state(frequency.into());
     }
 
+    pub fn tec_frequency(&self) -> TecFrequency {
+        if self.tec_freq.is_set_high() {
+            TecFrequency::High
+        } else {
+            TecFrequency::Low
+        }
+    }
+
     pub fn overtemp(&self) -> bool {
         self.overtemp.is_low()
     }
@@ -1,10 +1,12 @@
 use serde::Serialize;
 
+use super::gpio::TecFrequency;
+
 mod build_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Copy, Clone)]
 pub struct ApplicationMetadata {
     pub firmware_version: &'static str,
     pub rust_version: &'static str,
@@ -13,6 +15,10 @@ pub struct ApplicationMetadata {
     pub features: &'static str,
     pub panic_info: &'static str,
     pub hardware_version: u8,
+    /// TEC driver switching frequency. Updated by the telemetry client.
+    pub tec_frequency: TecFrequency,
+    /// The last reset was caused by the watchdog.
+    pub watchdog_reset: bool,
 }
 
 impl ApplicationMetadata {
@@ -23,10 +29,16 @@ impl ApplicationMetadata {
     ///
     /// # Args
     /// * `hardware_version` - The hardware version detected.
+    /// * `watchdog_reset` - The last reset was caused by the watchdog.
+    /// * `panic_info` - Panic message retained from the previous boot.
     ///
     /// # Returns
     /// A reference to the global metadata.
-    pub fn new(version: u8) -> &'static ApplicationMetadata {
+    pub fn new(
+        version: u8,
+        watchdog_reset: bool,
+        panic_info: Option<&'static str>,
+    ) -> &'static ApplicationMetadata {
         cortex_m::singleton!(: ApplicationMetadata = ApplicationMetadata {
             firmware_version: build_info::GIT_VERSION.unwrap_or("Unspecified"),
             rust_version: build_info::RUSTC_VERSION,
@@ -34,7 +46,9 @@ impl ApplicationMetadata {
             git_dirty: build_info::GIT_DIRTY.unwrap_or(false),
             features: build_info::FEATURES_STR,
             hardware_version: version,
-            panic_info: panic_persist::get_panic_message_utf8().unwrap_or("None"),
+            tec_frequency: TecFrequency::Low,
+            watchdog_reset,
+            panic_info: panic_info.unwrap_or("None"),
         })
         .unwrap()
     }
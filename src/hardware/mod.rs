@@ -9,7 +9,6 @@ pub mod adc;
 pub mod adc_internal;
 pub mod dac;
 pub mod delay;
-pub mod dt670;
 pub mod fan;
 pub mod flash;
 pub mod gpio;
@@ -18,6 +17,7 @@ pub mod platform;
 pub mod pwm;
 pub mod setup;
 pub mod system_timer;
+pub mod watchdog;
 
 // Number of TX descriptors in the ethernet descriptor ring.
 const TX_DESRING_CNT: usize = 4;
@@ -39,6 +39,9 @@ pub type NetworkManager = smoltcp_nal::shared::NetworkManager<
 
 pub type EthernetPhy = hal::ethernet::phy::LAN8742A<hal::ethernet::EthernetMAC>;
 
+/// Board temperature sensor on I2C1.
+pub type Lm75 = lm75::Lm75<hal::i2c::I2c<hal::stm32::I2C1>, lm75::ic::Lm75>;
+
 #[derive(Clone, Copy, strum::EnumIter, Debug)]
 #[repr(usize)]
 pub enum OutputChannelIdx {
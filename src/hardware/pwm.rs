@@ -16,6 +16,7 @@ use super::{
     },
     OutputChannelIdx,
 };
+use thermostat_control::tec;
 
 /// TEC limit types
 ///
@@ -77,8 +78,8 @@ impl Pwm {
     // PWM constants
     const MAX_DUTY: u32 = 10000; // Maximum duty cycle valid for all channels.
     const V_PWM: f32 = 3.3; // MCU PWM pin output high voltage
-    pub const MAX_CURRENT_LIMIT: f32 = 3.0; // As per MAX1968 datasheet. (Pwm::V_PWM * 0.15) / (VREF_TEC * R_SENSE) for 100% duty cycle equivalent.
-    pub const MAX_VOLTAGE_LIMIT: f32 = 4.3; // As per MAX1968 datasheet
+    pub const MAX_CURRENT_LIMIT: f32 = tec::MAX_CURRENT_LIMIT;
+    pub const MAX_VOLTAGE_LIMIT: f32 = tec::MAX_VOLTAGE_LIMIT;
 
     /// Construct a new PWM driver for all Thermostat output channel limits.
     ///
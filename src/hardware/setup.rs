@@ -11,6 +11,7 @@ use super::hal::{
     self as hal,
     ethernet::{self, PHY},
     gpio::{GpioExt, Speed},
+    independent_watchdog::IndependentWatchdog,
     prelude::*,
 };
 
@@ -23,7 +24,7 @@ use super::{
     gpio::Gpio,
     metadata::ApplicationMetadata,
     pwm::{Pwm, PwmPins},
-    EthernetPhy, NetworkStack, Systick,
+    EthernetPhy, Lm75, NetworkStack, Systick,
 };
 
 use log::info;
@@ -105,6 +106,7 @@ pub struct ThermostatDevices<C: serial_settings::Settings + 'static, const Y: us
     pub pwm: Pwm,
     pub gpio: Gpio,
     pub fan: Fan,
+    pub lm75: Lm75,
     pub adc_internal: AdcInternal,
     pub adc_sm: StateMachine<Adc>,
     pub adc_input_config: AdcConfig,
@@ -112,6 +114,11 @@ pub struct ThermostatDevices<C: serial_settings::Settings + 'static, const Y: us
     pub usb: super::UsbDevice,
     pub metadata: &'static ApplicationMetadata,
     pub settings: C,
+    pub watchdog: IndependentWatchdog,
+    /// The last reset was caused by the watchdog.
+    pub watchdog_reset: bool,
+    /// Panic message retained from the previous boot.
+    pub panic_info: Option<&'static str>,
 }
 
 #[link_section = ".sram3.eth"]
@@ -185,6 +192,11 @@ where
 
     device.RCC.d3ccipr.modify(|_, w| w.adcsel().per());
 
+    let watchdog_reset = device.RCC.rsr.read().iwdg1rstf().bit_is_set();
+    if watchdog_reset {
+        log::warn!("Watchdog reset");
+    }
+
     // Clear reset flags.
     device.RCC.rsr.write(|w| w.rmvf().set_bit());
 
@@ -453,14 +465,16 @@ where
         // wrong ESD protection https://github.com/sinara-hw/Thermostat_EEM/issues/51
         log::warn!("I2C failure, using default MAC");
         eui48 = [0x02, 0x00, 0x00, 0x00, 0x00, 0xd3];
+    } else if let Ok(()) = afe_i2c.write_read(0x50, &[0xFA], &mut eui48) {
+        log::info!("AFE EUI48: {}", smoltcp::wire::EthernetAddress(eui48));
     } else {
-        let mut lm75 = lm75::Lm75::new(i2c, lm75::Address::default());
-        log::info!("LM75 Temperature: {}°C", lm75.read_temperature().unwrap());
-        if let Ok(()) = afe_i2c.write_read(0x50, &[0xFA], &mut eui48) {
-            log::info!("AFE EUI48: {}", smoltcp::wire::EthernetAddress(eui48));
-        } else {
-            log::warn!("AFE EUI48 read failure.");
-        }
+        log::warn!("AFE EUI48 read failure.");
+    }
+
+    let mut lm75 = lm75::Lm75::new(i2c, lm75::Address::default());
+    match lm75.read_temperature() {
+        Ok(temperature) => log::info!("LM75 Temperature: {}°C", temperature),
+        Err(e) => log::warn!("LM75 read failure: {:?}", e),
     }
     let mac_addr = smoltcp::wire::EthernetAddress(eui48);
     log::info!("EUI48: {}", mac_addr);
@@ -473,6 +487,7 @@ where
 
         let mut settings = C::new(crate::NetSettings::new(mac_addr));
         crate::settings::SerialSettingsPlatform::<_, Y>::load(&mut settings, &mut flash);
+        crate::events::check(&mut flash);
         (flash, settings)
     };
 
@@ -673,7 +688,9 @@ where
         (usb_device, serial)
     };
 
-    let metadata = ApplicationMetadata::new(gpio.hwrev());
+    // Note: Reading the panic message clears it.
+    let panic_info = panic_persist::get_panic_message_utf8();
+    let metadata = ApplicationMetadata::new(gpio.hwrev(), watchdog_reset, panic_info);
 
     let usb_terminal = {
         let input_buffer = cortex_m::singleton!(: [u8; 128] = [0u8; 128]).unwrap();
@@ -702,6 +719,7 @@ where
         pwm,
         gpio,
         fan,
+        lm75,
         adc_internal,
         adc_sm,
         adc_input_config,
@@ -709,5 +727,8 @@ where
         settings,
         usb: usb_device,
         metadata,
+        watchdog: IndependentWatchdog::new(device.IWDG),
+        watchdog_reset,
+        panic_info,
     }
 }
@@ -0,0 +1,109 @@
+//! Independent watchdog and task supervisor.
+//!
+//! The IWDG resets the device unless it is fed periodically. The supervisor only feeds it
+//! while all supervised tasks report progress (heartbeats) within their timeouts. A hung
+//! task, e.g. a stuck SPI transfer, therefore leads to a reset and a defined safe state
+//! (all TEC drivers in shutdown).
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use strum::IntoEnumIterator;
+
+use super::hal::{independent_watchdog::IndependentWatchdog, prelude::*};
+
+/// Supervised tasks.
+#[derive(Copy, Clone, Debug, strum::EnumIter)]
+#[repr(usize)]
+pub enum Task {
+    /// ADC data processing
+    Process = 0,
+    /// Telemetry
+    Telemetry = 1,
+    /// Network loop in `idle`
+    Idle = 2,
+}
+
+/// Heartbeat counters.
+static BEATS: [AtomicU32; 3] = [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)];
+
+/// Heartbeat timeouts in milliseconds.
+/// The timeouts account for blocking flash operations at lower priority.
+static TIMEOUTS: [AtomicU32; 3] = [
+    AtomicU32::new(1_000),
+    AtomicU32::new(10_000),
+    AtomicU32::new(10_000),
+];
+
+/// Report progress of a task.
+pub fn beat(task: Task) {
+    BEATS[task as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Set the heartbeat timeout of a task.
+///
+/// # Args
+/// * `task` - The supervised task
+/// * `timeout` - Maximum time between heartbeats in milliseconds
+pub fn set_timeout(task: Task, timeout: u32) {
+    TIMEOUTS[task as usize].store(timeout, Ordering::Relaxed);
+}
+
+pub struct Supervisor {
+    watchdog: IndependentWatchdog,
+    /// Heartbeat counts at the last progress.
+    beats: [u32; 3],
+    /// Time of the last progress in milliseconds.
+    last: [u32; 3],
+    /// A stall has been detected. The watchdog is not fed anymore.
+    stalled: Option<Task>,
+}
+
+impl Supervisor {
+    /// Watchdog timeout in milliseconds.
+    const TIMEOUT: u32 = 1_000;
+
+    /// Start the watchdog.
+    ///
+    /// # Note
+    /// The watchdog can not be stopped once started.
+    ///
+    /// # Args
+    /// * `watchdog` - The independent watchdog
+    /// * `now` - Current time in milliseconds
+    pub fn new(mut watchdog: IndependentWatchdog, now: u32) -> Self {
+        watchdog.start(Self::TIMEOUT.millis());
+        Self {
+            watchdog,
+            beats: core::array::from_fn(|i| BEATS[i].load(Ordering::Relaxed)),
+            last: [now; 3],
+            stalled: None,
+        }
+    }
+
+    /// Check task progress and feed the watchdog.
+    ///
+    /// Must be called at a period well below the watchdog timeout.
+    ///
+    /// # Args
+    /// * `now` - Current time in milliseconds
+    ///
+    /// # Returns
+    /// The first stalled task. The watchdog is not fed anymore once a task has stalled.
+    pub fn update(&mut self, now: u32) -> Option<Task> {
+        for task in Task::iter() {
+            let idx = task as usize;
+            let beats = BEATS[idx].load(Ordering::Relaxed);
+            if beats != self.beats[idx] {
+                self.beats[idx] = beats;
+                self.last[idx] = now;
+            } else if self.stalled.is_none()
+                && now.wrapping_sub(self.last[idx]) > TIMEOUTS[idx].load(Ordering::Relaxed)
+            {
+                self.stalled = Some(task);
+            }
+        }
+        if self.stalled.is_none() {
+            self.watchdog.feed();
+        }
+        self.stalled
+    }
+}
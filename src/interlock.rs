@@ -0,0 +1,54 @@
+//! # Thermostat_EEM hardware overtemperature interlock
+//!
+//! The board overtemperature signal shuts all outputs down. The interlock latches and keeps
+//! the outputs in shutdown until it is explicitly acknowledged.
+
+use serde::Serialize;
+
+/// Overtemperature interlock state and trip history.
+#[derive(Serialize, Copy, Clone, Default, Debug)]
+pub struct Interlock {
+    /// The interlock is tripped. All outputs are held in shutdown until acknowledged.
+    tripped: bool,
+    /// Number of trips since boot.
+    trips: u32,
+    /// Uptime in seconds of the most recent trips, most recent first.
+    history: [Option<u32>; 4],
+}
+
+impl Interlock {
+    /// Whether the interlock is tripped.
+    pub fn tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// Trip the interlock.
+    ///
+    /// # Args
+    /// * `uptime` - Time since boot in seconds
+    ///
+    /// # Returns
+    /// True if the interlock was not tripped before.
+    pub fn trip(&mut self, uptime: u32) -> bool {
+        if self.tripped {
+            return false;
+        }
+        self.tripped = true;
+        self.trips = self.trips.saturating_add(1);
+        self.history.rotate_right(1);
+        self.history[0] = Some(uptime);
+        true
+    }
+
+    /// Acknowledge a tripped interlock.
+    ///
+    /// # Args
+    /// * `asserted` - The overtemperature signal is currently asserted.
+    ///
+    /// # Returns
+    /// True if the interlock is cleared. It stays tripped while the signal is asserted.
+    pub fn acknowledge(&mut self, asserted: bool) -> bool {
+        self.tripped &= asserted;
+        !self.tripped
+    }
+}
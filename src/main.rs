@@ -5,39 +5,56 @@
 #![no_std]
 #![no_main]
 
+pub mod events;
 pub mod hardware;
+pub mod interlock;
 pub mod net;
-pub mod output_channel;
 pub mod settings;
 pub mod statistics;
 
+pub use thermostat_control::{alarm, derating, excitation, fan, output_channel, rate, supply};
+
+use core::fmt::Write;
+use heapless::String;
 use panic_probe as _; // global panic handler
 use strum::IntoEnumIterator;
 
+use alarm::{Action, LimitState, OutputAction, Severity, Violation};
+use derating::Derating;
+use events::{Event, Record, Request};
+use excitation::{ExcitationState, Response};
+use fan::{FanControl, Mode as FanMode, StallState};
 use hardware::{
     adc::AdcPhy,
     adc::{sm::StateMachine, Adc, AdcCode, Ntc, Sensor},
     adc_internal::AdcInternal,
-    dac::{Dac, DacCode},
-    gpio::{Gpio, PoePower},
+    dac::{Affine, Dac, DacCode, Error as DacError},
+    fan::Fan,
+    gpio::{Gpio, PoePower, TecFrequency},
     hal,
     pwm::{Limit, Pwm},
-    OutputChannelIdx, SerialTerminal, SystemTimer, Systick, UsbDevice,
+    watchdog::{self, Supervisor},
+    Lm75, OutputChannelIdx, SerialTerminal, SystemTimer, Systick, UsbDevice,
 };
 
 use rtic_monotonics::Monotonic;
 use rtic_sync::{channel::*, make_channel};
 
 use fugit::ExtU32;
+use interlock::Interlock;
 use miniconf::{Leaf, StrLeaf, TreeDeserialize, TreeKey, TreeSerialize};
 use net::{
     data_stream::{FrameGenerator, StreamFormat, StreamTarget},
-    Alarm, NetworkState, NetworkUsers,
+    telemetry::TelemetryClient,
+    Alarm, Evaluation, NetworkState, NetworkUsers, Payload, Source,
 };
-use output_channel::{OutputChannel, State};
+use num_traits::Float;
+use output_channel::{Fault, MismatchState, OutputChannel, RunawayState, SettleState, Slew, State};
+use rate::Rate;
 use serde::Serialize;
 use settings::NetSettings;
 use statistics::{Buffer, Statistics};
+use supply::{Supply, Throttle};
 
 #[derive(Clone, Debug, TreeSerialize, TreeDeserialize, TreeKey, Default)]
 pub struct InputChannel {
@@ -55,9 +72,43 @@ pub struct ThermostatEem {
     /// `telemetry_period`
     ///
     /// # Value
-    /// Any positive non-zero value. Will be rounded to milliseconds.
+    /// At least 0.1 s and finite. Will be rounded to milliseconds.
+    #[tree(validate=self.validate_telemetry_period)]
     telemetry_period: Leaf<f32>,
 
+    /// TEC driver switching frequency.
+    ///
+    /// All outputs are briefly shut down while the frequency is changed.
+    ///
+    /// # Path
+    /// `tec_frequency`
+    ///
+    /// # Value
+    /// `"Low"` (~500 kHz) or `"High"` (~1 MHz). See [TecFrequency].
+    tec_frequency: Leaf<TecFrequency>,
+
+    /// Acknowledge a tripped overtemperature interlock.
+    ///
+    /// Setting `true` clears the interlock unless the overtemperature signal is still
+    /// asserted. The value is reset to `false` once processed.
+    ///
+    /// # Path
+    /// `acknowledge`
+    ///
+    /// # Value
+    /// `true` or `false`
+    acknowledge: Leaf<bool>,
+
+    /// Resume operation with the stored output states after a watchdog reset.
+    /// Otherwise all outputs are kept `Off` until their state is set again.
+    ///
+    /// # Path
+    /// `resume`
+    ///
+    /// # Value
+    /// `true` or `false`
+    resume: Leaf<bool>,
+
     /// Input sensor configuration
     input: [[Option<InputChannel>; 4]; 4],
 
@@ -80,16 +131,68 @@ pub struct ThermostatEem {
     /// See [Alarm]
     alarm: Alarm,
 
+    /// Fan control settings.
+    ///
+    /// # Path
+    /// `fan`
+    ///
+    /// # Value
+    /// See [FanControl]
+    fan: FanControl,
+
+    /// Output current derating with board temperature.
+    ///
+    /// # Path
+    /// `derating`
+    ///
+    /// # Value
+    /// See [Derating]
+    derating: Derating,
+
+    /// Supply rail thresholds and input power budget.
+    ///
+    /// # Path
+    /// `supply`
+    ///
+    /// # Value
+    /// See [Supply]
+    supply: Supply,
+
     stream: Leaf<StreamTarget>,
 }
 
+impl ThermostatEem {
+    /// Minimum telemetry period in seconds.
+    const MIN_TELEMETRY_PERIOD: f32 = 0.1;
+
+    fn validate_telemetry_period(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if !(self.telemetry_period.is_finite()
+            && *self.telemetry_period >= Self::MIN_TELEMETRY_PERIOD)
+        {
+            return Err("Telemetry period must be finite and at least 0.1 s.");
+        }
+        Ok(depth)
+    }
+
+    /// Telemetry period in milliseconds.
+    fn telemetry_period_ms(&self) -> u32 {
+        (*self.telemetry_period * 1000.0).round() as _
+    }
+}
+
 impl Default for ThermostatEem {
     fn default() -> Self {
         Self {
             telemetry_period: 1.0.into(),
+            tec_frequency: Default::default(),
+            acknowledge: false.into(),
+            resume: false.into(),
             input: Default::default(),
             output: Default::default(),
             alarm: Default::default(),
+            fan: Default::default(),
+            derating: Default::default(),
+            supply: Default::default(),
             stream: Default::default(),
         }
     }
@@ -141,6 +244,53 @@ pub struct Monitor {
     poe: PoePower,
     /// Overtemperature status.
     overtemp: bool,
+    /// Board temperature in °C measured by the LM75. `None` on read failure.
+    board_temperature: Option<f32>,
+    /// TEC driver switching frequency.
+    tec_frequency: TecFrequency,
+    /// Number of ADC conversion errors since boot.
+    adc_errors: u32,
+    /// Number of network link losses since boot.
+    link_losses: u32,
+}
+
+/// Electrical power and energy derived from the [Monitor] measurements.
+#[derive(Serialize, Copy, Clone, Default, Debug)]
+pub struct Power {
+    /// TEC electrical power (voltage times current) for each output channel in Watt.
+    output_power: [f32; 4],
+    /// TEC module resistance (voltage over current) for each output channel in Ohm.
+    /// `None` if the output current is too small for a meaningful measurement.
+    output_resistance: [Option<f32>; 4],
+    /// TEC electrical energy since boot for each output channel in Joule.
+    output_energy: [f64; 4],
+    /// Input power from the 12 V supply in Watt.
+    input_power: f32,
+    /// Input energy since boot in Joule.
+    input_energy: f64,
+}
+
+impl Power {
+    /// Compute power and resistance and accumulate energy.
+    ///
+    /// # Args
+    /// * `monitor` - Monitor measurements
+    /// * `elapsed` - Time since the last update in seconds
+    fn update(&mut self, monitor: &Monitor, elapsed: f32) {
+        for (idx, (voltage, current)) in monitor
+            .output_voltage
+            .iter()
+            .zip(monitor.output_current.iter())
+            .enumerate()
+        {
+            self.output_power[idx] = voltage * current;
+            self.output_resistance[idx] = (current.abs() >= OutputChannel::MIN_MEASUREMENT_CURRENT)
+                .then(|| voltage / current);
+            self.output_energy[idx] += (self.output_power[idx] * elapsed) as f64;
+        }
+        self.input_power = monitor.p12v_voltage * monitor.p12v_current;
+        self.input_energy += (self.input_power * elapsed) as f64;
+    }
 }
 
 /// Thermostat-EEM Telemetry.
@@ -148,12 +298,221 @@ pub struct Monitor {
 pub struct Telemetry {
     /// see [Monitor]
     monitor: Monitor,
+    /// see [Power]
+    power: Power,
+    /// see [FanStatus]
+    fan: FanStatus,
+    /// Output current scale factor due to the board temperature and the power budget.
+    /// See [Derating] and [Supply].
+    derating: f32,
+    /// see [SupplyStatus]
+    supply: SupplyStatus,
+    /// Overtemperature interlock state and trip history. See [Interlock].
+    interlock: Interlock,
     /// `[<adc>][<channel>]` array of [Statistics]. `None` for disabled channels.
     statistics: [[Option<Statistics>; 4]; 4],
     /// Alarm status for each enabled input channel. `None` for disabled channels.
     alarm: [[Option<bool>; 4]; 4],
+    /// Alarm status for each alarm source. `None` for disabled sources.
+    alarm_sources: [Option<bool>; 8],
     /// Output current in Amperes for each Thermostat output channel.
     output_current: [f32; 4],
+    /// Latched fault for each Thermostat output channel. `None` if there is no fault.
+    fault: [Option<Fault>; 4],
+    /// Loop settled state for each Thermostat output channel.
+    settled: [bool; 4],
+    /// Number of NaN or out of range output currents since boot for each Thermostat output channel.
+    dac_errors: [u32; 4],
+}
+
+impl Telemetry {
+    /// Current value of an alarm source.
+    ///
+    /// # Args
+    /// * `source` - The alarm source
+    /// * `index` - Output channel index
+    /// * `counts` - Number of ADC errors and link losses since the last alarm period
+    ///
+    /// # Returns
+    /// `None` if the source is disabled.
+    fn alarm_value(&self, source: Source, index: usize, counts: [u32; 2]) -> Option<f32> {
+        let monitor = &self.monitor;
+        Some(match source {
+            Source::None => return None,
+            Source::P3v3Voltage => monitor.p3v3_voltage,
+            Source::P5vVoltage => monitor.p5v_voltage,
+            Source::P12vVoltage => monitor.p12v_voltage,
+            Source::P12vCurrent => monitor.p12v_current,
+            Source::OutputCurrent => monitor.output_current[index],
+            Source::OutputVoltage => monitor.output_voltage[index],
+            Source::Overtemp => f32::from(u8::from(monitor.overtemp)),
+            Source::Fault => f32::from(u8::from(self.fault[index].is_some())),
+            Source::AdcErrors => counts[0] as _,
+            Source::LinkLosses => counts[1] as _,
+        })
+    }
+}
+
+/// Input power budget usage and supply rail status.
+#[derive(Serialize, Copy, Clone, Default, Debug)]
+pub struct SupplyStatus {
+    /// Input power budget in Watt for the current power source. `None` if unlimited.
+    budget: Option<f32>,
+    /// Input power relative to the budget.
+    usage: Option<f32>,
+    /// Output current scale factor due to the power budget. Throttled if below one.
+    throttle: f32,
+    /// A supply rail is below its threshold.
+    undervoltage: bool,
+}
+
+/// Input power budget in Watt for a power source.
+fn power_budget(supply: &Supply, poe: PoePower) -> f32 {
+    match poe {
+        PoePower::Absent => *supply.external,
+        PoePower::Low => *supply.poe_af,
+        PoePower::High => *supply.poe_at,
+    }
+}
+
+/// Input channel quantity checked by the alarm.
+#[derive(Serialize, Copy, Clone, Debug)]
+enum Quantity {
+    /// Temperature in °C
+    Temperature,
+    /// Rate of change of the temperature in K/s
+    Rate,
+}
+
+/// Origin of an asserted alarm limit.
+#[derive(Serialize, Copy, Clone, Debug)]
+enum Origin {
+    /// Input channel limit
+    Input {
+        /// ADC index
+        adc: u8,
+        /// Channel index of the ADC
+        channel: u8,
+        /// See [Quantity]
+        quantity: Quantity,
+    },
+    /// Alarm source, see [AlarmSource](net::AlarmSource)
+    Source {
+        /// See [Source]
+        source: Source,
+        /// Output channel index
+        index: u8,
+    },
+}
+
+/// Asserted alarm limit.
+#[derive(Serialize, Copy, Clone, Debug)]
+struct AlarmCause {
+    /// See [Origin]
+    origin: Origin,
+    /// See [Violation]
+    violation: Violation,
+}
+
+/// Asserted alarm limits.
+///
+/// The number of causes is bounded so that the JSON payload fits the telemetry MQTT buffer.
+#[derive(Clone, Debug, Default)]
+struct AlarmCauses {
+    /// Highest severity of all asserted limits. `None` if there is no alarm.
+    severity: Option<Severity>,
+    /// The first asserted limits.
+    causes: heapless::Vec<AlarmCause, 16>,
+    /// Asserted limits have been dropped from `causes`.
+    truncated: bool,
+}
+
+impl AlarmCauses {
+    /// Add an asserted limit.
+    fn push(&mut self, cause: AlarmCause) {
+        self.severity = self.severity.max(Some(cause.violation.severity));
+        self.truncated |= self.causes.push(cause).is_err();
+    }
+}
+
+/// Structured alarm payload.
+#[derive(Serialize, Clone, Debug)]
+struct AlarmPayload<'a> {
+    /// Alarm state.
+    alarm: bool,
+    /// Highest severity of the asserted limits. `None` if there is no alarm.
+    severity: Option<Severity>,
+    /// Asserted limits.
+    causes: &'a [AlarmCause],
+    /// Asserted limits have been dropped from `causes`.
+    truncated: bool,
+}
+
+/// Output action applied by the alarm.
+#[derive(Copy, Clone, Debug)]
+struct AppliedAction {
+    /// See [OutputAction]
+    action: OutputAction,
+    /// Output state and setpoint before the action.
+    previous: (State, f32),
+    /// Output state and setpoint set by the action.
+    applied: (State, f32),
+}
+
+/// Request the output action of an asserted alarm limit.
+///
+/// # Args
+/// * `requests` - Requested action for each output. The action with the highest precedence is kept.
+/// * `action` - Output action of the asserted limit
+fn request_action(requests: &mut [Option<OutputAction>; 4], action: &OutputAction) {
+    if *action.mode == Action::None {
+        return;
+    }
+    for (request, selected) in requests.iter_mut().zip(action.outputs.iter()) {
+        if *selected && request.is_none_or(|r| *r.mode < *action.mode) {
+            *request = Some(*action);
+        }
+    }
+}
+
+/// Publish an alarm state in the configured format.
+///
+/// # Args
+/// * `client` - The telemetry client
+/// * `target` - MQTT topic
+/// * `payload` - Payload format
+/// * `alarm` - Alarm state
+/// * `causes` - Asserted limits
+fn publish_alarm(
+    client: &mut TelemetryClient,
+    target: &String<128>,
+    payload: Payload,
+    alarm: bool,
+    causes: &AlarmCauses,
+) {
+    match payload {
+        Payload::Bool => client.publish_alarm(target, &alarm),
+        Payload::Json => client.publish_alarm(
+            target,
+            &AlarmPayload {
+                alarm,
+                severity: causes.severity,
+                causes: &causes.causes,
+                truncated: causes.truncated,
+            },
+        ),
+    }
+}
+
+/// Fan duty cycle, speed and fault.
+#[derive(Serialize, Copy, Clone, Default, Debug)]
+pub struct FanStatus {
+    /// Fan PWM duty cycle.
+    duty: f32,
+    /// Fan speed in RPM.
+    rpm: f32,
+    /// Latched stalled fan fault. Acknowledged by setting the fan `mode` to `Off`.
+    stalled: bool,
 }
 
 #[repr(C)]
@@ -170,6 +529,32 @@ struct Data {
     adc_code: AdcCode,
 }
 
+/// Latch a fault on an output channel and put its TEC driver into shutdown if configured.
+///
+/// The first fault is retained until it is acknowledged by setting the output `state` to `Off`.
+/// A fault that does not shut the output down is superseded by one that does.
+fn trip(
+    fault: &mut [Option<Fault>; 4],
+    gpio: &mut Gpio,
+    ch: OutputChannelIdx,
+    output: &OutputChannel,
+    cause: Fault,
+) {
+    if fault[ch as usize].is_none_or(|f| !output.fault_shutdown(f) && output.fault_shutdown(cause))
+    {
+        log::warn!("Output {:?} fault: {:?}", ch, cause);
+        fault[ch as usize] = Some(cause);
+        events::record(Event::Fault {
+            output: ch as u8,
+            fault: cause,
+        });
+    }
+    if output.fault_shutdown(cause) {
+        gpio.set_shutdown(ch, true.into());
+        gpio.set_led(ch.into(), false.into());
+    }
+}
+
 #[rtic::app(device = hal::stm32, peripherals = true, dispatchers=[DCMI, JPEG, SDMMC])]
 mod app {
     use super::*;
@@ -183,6 +568,11 @@ mod app {
         gpio: Gpio,
         temperature: [[f64; 4]; 4], // input temperature array in °C. Organized as [Adc_idx,  Channel_idx].
         statistics: [[Buffer; 4]; 4], // input statistics buffer for processing telemetry. Organized as [Adc_idx,  Channel_idx].
+        alarm_statistics: [[Buffer; 4]; 4], // input statistics buffer over the alarm period. Organized as [Adc_idx,  Channel_idx].
+        fault: [Option<Fault>; 4],          // latched output channel faults
+        adc_internal: AdcInternal,
+        fan: Fan,
+        interlock: Interlock,
     }
 
     #[local]
@@ -191,10 +581,20 @@ mod app {
         adc_sm: StateMachine<Adc>,
         dac: Dac,
         pwm: Pwm,
-        adc_internal: AdcInternal,
+        lm75: Lm75,
         iir_state: [[f64; 4]; 4],
+        rate: [[Rate; 4]; 4],
+        slew: [Slew; 4],
+        runaway: [RunawayState; 4],
+        settle: [SettleState; 4],
+        excitation: [ExcitationState; 4],
         generator: FrameGenerator,
         process: Sender<'static, Data, 4>,
+        settled: Sender<'static, (OutputChannelIdx, bool), 4>,
+        response: Sender<'static, (OutputChannelIdx, Response), 2>,
+        calibrated: Sender<'static, OutputChannelIdx, 4>,
+        calibration_store: Receiver<'static, OutputChannelIdx, 4>,
+        supervisor: Supervisor,
     }
 
     #[init]
@@ -223,6 +623,26 @@ mod app {
             }
         }
 
+        events::record(Event::Boot {
+            watchdog: thermostat.watchdog_reset,
+        });
+        if let Some(panic) = thermostat.panic_info {
+            let mut message = String::new();
+            for c in panic.chars() {
+                if message.push(c).is_err() {
+                    break;
+                }
+            }
+            events::record(Event::Panic { message });
+        }
+
+        if thermostat.watchdog_reset && !*thermostat.settings.thermostat_eem.resume {
+            log::warn!("Keeping outputs off after watchdog reset");
+            for output in thermostat.settings.thermostat_eem.output.iter_mut() {
+                *output.state = State::Off;
+            }
+        }
+
         let mut network = NetworkUsers::new(
             thermostat.net.stack,
             thermostat.net.phy,
@@ -235,16 +655,29 @@ mod app {
         let generator = network.configure_streaming(StreamFormat::ThermostatEem as _);
 
         let (process, r) = make_channel!(Data, 4);
+        let (settled, settled_r) = make_channel!((OutputChannelIdx, bool), 4);
+        let (response, response_r) = make_channel!((OutputChannelIdx, Response), 2);
+        let (calibrated, calibration_store) = make_channel!(OutputChannelIdx, 4);
 
         let local = Local {
             usb_terminal: thermostat.usb_serial,
             adc_sm: thermostat.adc_sm,
             pwm: thermostat.pwm,
-            adc_internal: thermostat.adc_internal,
+            lm75: thermostat.lm75,
             iir_state: Default::default(),
+            rate: Default::default(),
+            slew: Default::default(),
+            runaway: Default::default(),
+            settle: Default::default(),
+            excitation: Default::default(),
             dac: thermostat.dac,
             generator,
             process,
+            settled,
+            response,
+            calibrated,
+            calibration_store,
+            supervisor: Supervisor::new(thermostat.watchdog, Systick::now().ticks()),
         };
 
         let shared = Shared {
@@ -255,16 +688,25 @@ mod app {
             gpio: thermostat.gpio,
             temperature: Default::default(),
             statistics: Default::default(),
+            alarm_statistics: Default::default(),
+            fault: Default::default(),
+            adc_internal: thermostat.adc_internal,
+            fan: thermostat.fan,
+            interlock: Default::default(),
         };
 
         process::spawn(r).unwrap();
+        publish_settled::spawn(settled_r).unwrap();
+        publish_response::spawn(response_r).unwrap();
 
         // Apply initial settings
         settings::spawn().unwrap();
         ethernet_link::spawn().unwrap();
         telemetry::spawn().unwrap();
+        overtemp::spawn().unwrap();
         alarm::spawn().unwrap();
         usb::spawn().unwrap();
+        supervisor::spawn().unwrap();
 
         (shared, local)
     }
@@ -272,9 +714,14 @@ mod app {
     #[idle(shared=[network, settings])]
     fn idle(mut c: idle::Context) -> ! {
         loop {
+            watchdog::beat(watchdog::Task::Idle);
             (&mut c.shared.network, &mut c.shared.settings).lock(|net, settings| {
                 match net.update(&mut settings.thermostat_eem) {
-                    NetworkState::SettingsChanged => settings::spawn().unwrap(),
+                    NetworkState::SettingsChanged => {
+                        events::record(Event::Settings);
+                        // A pending run (spawned by another task) applies the change as well.
+                        settings::spawn().ok();
+                    }
                     NetworkState::Updated => {}
                     NetworkState::NoChange => {}
                 }
@@ -282,42 +729,220 @@ mod app {
         }
     }
 
-    #[task(priority = 1, local=[pwm], shared=[network, settings, gpio])]
-    async fn settings(c: settings::Context) {
+    #[task(priority = 1, local=[pwm], shared=[network, settings, gpio, fault, interlock])]
+    async fn settings(mut c: settings::Context) {
         let pwm = c.local.pwm;
-        (c.shared.network, c.shared.gpio, c.shared.settings).lock(|network, gpio, settings| {
-            for (ch, s) in OutputChannelIdx::iter().zip(settings.thermostat_eem.output.iter_mut()) {
-                pwm.set_limit(Limit::Voltage(ch), *s.voltage_limit).unwrap();
-                let [pos, neg] = s.current_limits();
-                pwm.set_limit(Limit::PositiveCurrent(ch), pos).unwrap();
-                pwm.set_limit(Limit::NegativeCurrent(ch), neg).unwrap();
-                gpio.set_shutdown(ch, (*s.state == State::Off).into());
-                gpio.set_led(ch.into(), (*s.state != State::Off).into()); // fix leds to channel state
-            }
+        let frequency = c.shared.settings.lock(|s| *s.thermostat_eem.tec_frequency);
+        (
+            c.shared.network,
+            c.shared.gpio,
+            c.shared.settings,
+            c.shared.fault,
+            c.shared.interlock,
+        )
+            .lock(|network, gpio, settings, fault, interlock| {
+                // Keep all outputs shut down while the TEC driver switching frequency changes.
+                // The `tec_frequency` task runs this task again once the frequency is switched.
+                let switching = gpio.tec_frequency() != frequency;
+                if switching {
+                    tec_frequency::spawn(frequency).ok();
+                }
+                if *settings.thermostat_eem.acknowledge {
+                    *settings.thermostat_eem.acknowledge = false;
+                    if interlock.tripped() && interlock.acknowledge(gpio.overtemp()) {
+                        log::info!("Overtemperature interlock acknowledged");
+                    }
+                }
+                // Cap the output currents to the power budget before enabling outputs.
+                let supply = &settings.thermostat_eem.supply;
+                let cap = supply.current_cap(
+                    power_budget(supply, gpio.poe()),
+                    settings.thermostat_eem.output.each_ref().map(|s| {
+                        (*s.state != State::Off && !s.inhibit).then_some(*s.voltage_limit)
+                    }),
+                );
+                for ((ch, s), cap) in OutputChannelIdx::iter()
+                    .zip(settings.thermostat_eem.output.iter_mut())
+                    .zip(cap)
+                {
+                    s.current_cap = cap;
+                    pwm.set_limit(Limit::Voltage(ch), *s.voltage_limit).unwrap();
+                    let [pos, neg] = s.current_limits();
+                    pwm.set_limit(Limit::PositiveCurrent(ch), pos).unwrap();
+                    pwm.set_limit(Limit::NegativeCurrent(ch), neg).unwrap();
+                    // Switching the output off acknowledges a latched fault.
+                    if *s.state == State::Off {
+                        fault[ch as usize] = None;
+                    }
+                    let shutdown = *s.state == State::Off
+                        || s.inhibit
+                        || switching
+                        || interlock.tripped()
+                        || fault[ch as usize].is_some_and(|f| s.fault_shutdown(f));
+                    gpio.set_shutdown(ch, shutdown.into());
+                    gpio.set_led(ch.into(), (!shutdown).into()); // fix leds to channel state
+                    if *s.state == State::Calibrate && !shutdown {
+                        // Fails if the routine is already running on the channel.
+                        calibrate::spawn(ch).ok();
+                    }
+                }
 
-            network.direct_stream(*settings.thermostat_eem.stream);
-        });
+                network.direct_stream(*settings.thermostat_eem.stream);
+                network.telemetry.set_tec_frequency(frequency);
+            });
+    }
+
+    /// Switch the TEC driver frequency.
+    ///
+    /// The outputs are shut down by the settings task before the switch. They are re-enabled
+    /// by running the settings task again.
+    #[task(priority = 1, shared=[gpio])]
+    async fn tec_frequency(mut c: tec_frequency::Context, frequency: TecFrequency) {
+        // Let the `process` task see the shutdown.
+        Systick::delay(2.millis()).await;
+        log::info!("TEC frequency: {:?}", frequency);
+        c.shared.gpio.lock(|gpio| gpio.set_tec_frequency(frequency));
+        settings::spawn().ok();
     }
 
-    #[task(priority = 1, local=[adc_internal], shared=[network, settings, telemetry, gpio, statistics])]
+    #[task(priority = 1, local=[lm75], shared=[network, settings, telemetry, gpio, statistics, fault, adc_internal, fan, interlock])]
     async fn telemetry(mut c: telemetry::Context) {
+        let mut power = Power::default();
+        let mut stall = StallState::default();
+        let mut throttle = Throttle::default();
+        let mut last_budget = None;
+        let mut stalled = false;
+        let mut mismatch: [MismatchState; 4] = Default::default();
+        let mut last = Systick::now();
         loop {
             let mut telemetry: Telemetry = c.shared.telemetry.lock(|telemetry| *telemetry);
-            let adc_int = &mut c.local.adc_internal;
-            telemetry.monitor.p3v3_voltage = adc_int.read_p3v3_voltage();
-            telemetry.monitor.p5v_voltage = adc_int.read_p5v_voltage();
-            telemetry.monitor.p12v_voltage = adc_int.read_p12v_voltage();
-            telemetry.monitor.p12v_current = adc_int.read_p12v_current();
-            for ch in OutputChannelIdx::iter() {
-                let idx = ch as usize;
-                telemetry.monitor.output_vref[idx] = adc_int.read_output_vref(ch);
-                telemetry.monitor.output_voltage[idx] = adc_int.read_output_voltage(ch);
-                telemetry.monitor.output_current[idx] = adc_int.read_output_current(ch);
-            }
+            let sense: [Affine; 4] = c.shared.settings.lock(|settings| {
+                core::array::from_fn(|idx| settings.thermostat_eem.output[idx].calibration.sense)
+            });
+            c.shared.adc_internal.lock(|adc_int| {
+                telemetry.monitor.p3v3_voltage = adc_int.read_p3v3_voltage();
+                telemetry.monitor.p5v_voltage = adc_int.read_p5v_voltage();
+                telemetry.monitor.p12v_voltage = adc_int.read_p12v_voltage();
+                telemetry.monitor.p12v_current = adc_int.read_p12v_current();
+                for ch in OutputChannelIdx::iter() {
+                    let idx = ch as usize;
+                    telemetry.monitor.output_vref[idx] = adc_int.read_output_vref(ch);
+                    telemetry.monitor.output_voltage[idx] = adc_int.read_output_voltage(ch);
+                    telemetry.monitor.output_current[idx] =
+                        adc_int.read_output_current(ch, &sense[idx]);
+                }
+            });
+            c.shared.settings.lock(|settings| {
+                for (output, (voltage, current)) in settings.thermostat_eem.output.iter_mut().zip(
+                    telemetry
+                        .monitor
+                        .output_voltage
+                        .iter()
+                        .zip(telemetry.monitor.output_current.iter()),
+                ) {
+                    output.measure_resistance(*voltage, *current);
+                }
+            });
             c.shared.gpio.lock(|gpio| {
                 telemetry.monitor.overtemp = gpio.overtemp();
                 telemetry.monitor.poe = gpio.poe();
+                telemetry.monitor.tec_frequency = gpio.tec_frequency();
+            });
+            telemetry.monitor.adc_errors = hardware::adc::errors();
+            telemetry.monitor.link_losses = c
+                .shared
+                .network
+                .lock(|network| network.processor.link_losses());
+            let board = c.local.lm75.read_temperature().ok();
+            telemetry.monitor.board_temperature = board;
+            let now = Systick::now();
+            let elapsed = (now - last).to_micros() as f32 * 1e-6;
+            last = now;
+            power.update(&telemetry.monitor, elapsed);
+            telemetry.power = power;
+            let supply = c
+                .shared
+                .settings
+                .lock(|settings| settings.thermostat_eem.supply);
+            let budget = power_budget(&supply, telemetry.monitor.poe);
+            let scale = supply.throttle(&mut throttle, budget, power.input_power);
+            let undervoltage = supply.undervoltage(
+                telemetry.monitor.p3v3_voltage,
+                telemetry.monitor.p5v_voltage,
+                telemetry.monitor.p12v_voltage,
+            );
+            telemetry.supply = SupplyStatus {
+                budget: budget.is_finite().then_some(budget),
+                usage: budget.is_finite().then_some(power.input_power / budget),
+                throttle: scale,
+                undervoltage,
+            };
+            let derated = c.shared.settings.lock(|settings| {
+                let factor = settings.thermostat_eem.derating.factor(board) * scale;
+                telemetry.derating = factor;
+                let mut changed = false;
+                for output in settings.thermostat_eem.output.iter_mut() {
+                    changed |= output.derating != factor;
+                    output.derating = factor;
+                }
+                changed
             });
+            if derated {
+                log::info!("Output current derating: {}", telemetry.derating);
+            }
+            let rebudgeted = last_budget.replace(budget).is_some_and(|b| b != budget);
+            if rebudgeted {
+                log::info!("Power budget: {} W", budget);
+            }
+            if derated || rebudgeted {
+                // Apply the output current caps and the PWM current limits.
+                settings::spawn().ok();
+            }
+            telemetry.interlock = c.shared.interlock.lock(|interlock| *interlock);
+            let fan = c
+                .shared
+                .settings
+                .lock(|settings| settings.thermostat_eem.fan);
+            let tec_power = power.output_power.iter().map(|p| p.abs()).sum();
+            let duty = fan.output(board, tec_power);
+            let rpm = c.shared.fan.lock(|f| {
+                f.set_duty(duty).unwrap();
+                f.rpm()
+            });
+            // Switching the fan off acknowledges a stalled fan fault.
+            stalled &= *fan.mode != FanMode::Off;
+            if fan.stalled(&mut stall, duty, rpm, elapsed) && !stalled {
+                log::warn!("Fan stalled");
+                stalled = true;
+            }
+            telemetry.fan = FanStatus { duty, rpm, stalled };
+            (
+                &mut c.shared.settings,
+                &mut c.shared.gpio,
+                &mut c.shared.fault,
+            )
+                .lock(|settings, gpio, fault| {
+                    for ch in OutputChannelIdx::iter() {
+                        let idx = ch as usize;
+                        let output = &settings.thermostat_eem.output[idx];
+                        if undervoltage && !gpio.shutdown(ch) {
+                            trip(fault, gpio, ch, output, Fault::Supply);
+                        }
+                        if fault[idx].is_none_or(|f| !output.fault_shutdown(f))
+                            && output.mismatch(
+                                &mut mismatch[idx],
+                                telemetry.output_current[idx],
+                                telemetry.monitor.output_current[idx],
+                                telemetry.monitor.output_voltage[idx],
+                                gpio.shutdown(ch),
+                                elapsed,
+                            )
+                        {
+                            trip(fault, gpio, ch, output, Fault::Current);
+                        }
+                    }
+                });
+            telemetry.fault = c.shared.fault.lock(|fault| *fault);
 
             // Finalize temperature telemetry and reset buffer
             for phy_i in 0..4 {
@@ -333,51 +958,260 @@ mod app {
                 .network
                 .lock(|network| network.telemetry.publish(&telemetry));
 
-            // TODO: validate telemetry period.
-            let telemetry_period = c
-                .shared
-                .settings
-                .lock(|settings| settings.thermostat_eem.telemetry_period);
-            Systick::delay((*telemetry_period as u32).secs()).await;
+            watchdog::beat(watchdog::Task::Telemetry);
+            // Wait for the period. Period changes take effect immediately.
+            loop {
+                let period = c
+                    .shared
+                    .settings
+                    .lock(|settings| settings.thermostat_eem.telemetry_period_ms());
+                watchdog::set_timeout(watchdog::Task::Telemetry, period.saturating_add(10_000));
+                let elapsed = (Systick::now() - last).to_millis();
+                if elapsed >= period {
+                    break;
+                }
+                Systick::delay((period - elapsed).min(100).millis()).await;
+            }
         }
     }
 
-    #[task(priority = 1, shared=[network, settings, temperature, telemetry])]
+    #[task(priority = 1, shared=[network, settings, temperature, alarm_statistics, telemetry, gpio])]
     async fn alarm(mut c: alarm::Context) {
+        let mut actions: [Option<AppliedAction>; 4] = Default::default();
+        let mut flash = false;
+        // Input limit states organized as [Adc_idx, Channel_idx, Quantity].
+        let mut state: [[[LimitState; 2]; 4]; 4] = Default::default();
+        let mut source_state: [LimitState; 8] = Default::default();
+        // ADC errors and link losses at the last alarm period.
+        let mut last_counts = [0; 2];
+        let mut last = Systick::now();
         loop {
-            let alarm = c
-                .shared
-                .settings
-                .lock(|settings| settings.thermostat_eem.alarm.clone());
+            let (alarm, acknowledge) = c.shared.settings.lock(|settings| {
+                let alarm = &mut settings.thermostat_eem.alarm;
+                let acknowledge = core::mem::take(&mut *alarm.acknowledge);
+                (alarm.clone(), acknowledge)
+            });
+            let temperatures = c.shared.temperature.lock(|temp| *temp);
+            let buffers = c.shared.alarm_statistics.lock(core::mem::take);
+            let telemetry = c.shared.telemetry.lock(|telemetry| *telemetry);
+            let total = [telemetry.monitor.adc_errors, telemetry.monitor.link_losses];
+            let counts: [u32; 2] = core::array::from_fn(|i| total[i].wrapping_sub(last_counts[i]));
+            last_counts = total;
+            let now = Systick::now();
+            let elapsed = (now - last).to_micros() as f32 * 1e-6;
+            last = now;
+            if acknowledge {
+                log::info!("Alarm acknowledged");
+            }
+            if !*alarm.armed || acknowledge {
+                state = Default::default();
+                source_state = Default::default();
+            }
+            let mut requests = [None; 4];
             if *alarm.armed {
-                let temperatures = c.shared.temperature.lock(|temp| *temp);
                 let mut alarms = [[None; 4]; 4];
-                let alarm_state = alarm
+                let mut causes = AlarmCauses::default();
+                for (idx, ((((temperature_limit, rate_limit), state), a), (t, buffer))) in alarm
                     .temperature_limits
                     .as_flattened()
                     .iter()
-                    .zip(temperatures.as_flattened().iter())
+                    .zip(alarm.rate_limits.as_flattened().iter())
+                    .zip(state.as_flattened_mut().iter_mut())
                     .zip(alarms.as_flattened_mut().iter_mut())
-                    .any(|((l, t), a)| {
-                        *a = l.map(|l| !(*l[0]..*l[1]).contains(&(*t as _)));
-                        a.unwrap_or_default()
-                    });
-                c.shared
-                    .telemetry
-                    .lock(|telemetry| telemetry.alarm = alarms);
-                c.shared
-                    .network
-                    .lock(|net| net.telemetry.publish_alarm(&alarm.target, &alarm_state));
+                    .zip(
+                        temperatures
+                            .as_flattened()
+                            .iter()
+                            .zip(buffers.as_flattened().iter()),
+                    )
+                    .enumerate()
+                {
+                    let temperature = match *alarm.evaluation {
+                        Evaluation::Sample => Some([*t as f32; 2]),
+                        Evaluation::Mean => buffer.mean().map(|m| [m; 2]),
+                        Evaluation::Extreme => buffer.range(),
+                    };
+                    let rate = buffer.rate().map(|r| [r; 2]);
+                    for (((limit, state), value), quantity) in
+                        [temperature_limit.as_ref(), rate_limit.get()]
+                            .into_iter()
+                            .zip(state.iter_mut())
+                            .zip([temperature, rate])
+                            .zip([Quantity::Temperature, Quantity::Rate])
+                    {
+                        if let Some(l) = limit {
+                            // Without new samples the state is kept.
+                            let asserted = value.map_or(state.asserted(), |v| {
+                                l.update(state, v, elapsed, *alarm.latch)
+                            });
+                            *a = Some(a.unwrap_or_default() | asserted);
+                            if asserted {
+                                request_action(&mut requests, &l.action);
+                            }
+                            if let Some(violation) = l.violation(state) {
+                                causes.push(AlarmCause {
+                                    origin: Origin::Input {
+                                        adc: (idx / 4) as _,
+                                        channel: (idx % 4) as _,
+                                        quantity,
+                                    },
+                                    violation,
+                                });
+                            }
+                        }
+                    }
+                }
+                let mut sources = [None; 8];
+                for ((source, state), a) in alarm
+                    .sources
+                    .iter()
+                    .zip(source_state.iter_mut())
+                    .zip(sources.iter_mut())
+                {
+                    let Some(value) =
+                        telemetry.alarm_value(*source.source, *source.index as _, counts)
+                    else {
+                        *state = Default::default();
+                        continue;
+                    };
+                    let asserted = source
+                        .limit
+                        .update(state, [value; 2], elapsed, *alarm.latch);
+                    *a = Some(asserted);
+                    if asserted {
+                        request_action(&mut requests, &source.limit.action);
+                    }
+                    let mut cause = AlarmCauses::default();
+                    if let Some(violation) = source.limit.violation(state) {
+                        let origin = Origin::Source {
+                            source: *source.source,
+                            index: *source.index,
+                        };
+                        cause.push(AlarmCause { origin, violation });
+                        causes.push(AlarmCause { origin, violation });
+                    }
+                    if !source.target.is_empty() {
+                        c.shared.network.lock(|net| {
+                            publish_alarm(
+                                &mut net.telemetry,
+                                &source.target,
+                                *alarm.payload,
+                                asserted,
+                                &cause,
+                            )
+                        });
+                    }
+                }
+                let alarm_state = alarms
+                    .as_flattened()
+                    .iter()
+                    .chain(sources.iter())
+                    .any(|a| a.unwrap_or_default());
+                c.shared.telemetry.lock(|telemetry| {
+                    telemetry.alarm = alarms;
+                    telemetry.alarm_sources = sources;
+                });
+                c.shared.network.lock(|net| {
+                    publish_alarm(
+                        &mut net.telemetry,
+                        &alarm.target,
+                        *alarm.payload,
+                        alarm_state,
+                        &causes,
+                    )
+                });
             }
+
+            // Apply and revert output actions like the settings task.
+            let mut shutdown = [false; 4];
+            c.shared.gpio.lock(|gpio| {
+                for (ch, shutdown) in OutputChannelIdx::iter().zip(shutdown.iter_mut()) {
+                    *shutdown = gpio.shutdown(ch);
+                }
+            });
+            let changed = c.shared.settings.lock(|settings| {
+                let mut changed = false;
+                for (((ch, output), (request, action)), shutdown) in OutputChannelIdx::iter()
+                    .zip(settings.thermostat_eem.output.iter_mut())
+                    .zip(requests.iter().zip(actions.iter_mut()))
+                    .zip(shutdown)
+                {
+                    // Acknowledging the alarm releases a non-reverting `Off` action.
+                    if acknowledge && request.is_none() && output.inhibit {
+                        log::info!("Releasing alarm shutdown of output {:?}", ch);
+                        output.inhibit = false;
+                        changed = true;
+                    }
+                    match request {
+                        Some(request) if action.is_none_or(|a| a.action != *request) => {
+                            log::warn!("Alarm action on output {:?}: {:?}", ch, *request.mode);
+                            let previous = action
+                                .map_or((*output.state, *output.pid.setpoint), |a| a.previous);
+                            // `Hold` and `Setpoint` only act on outputs that are `On` (or held
+                            // by a previous action) and not shut down.
+                            let on = !shutdown
+                                && (*output.state == State::On
+                                    || action.is_some_and(|a| {
+                                        a.previous.0 == State::On && a.applied.0 == *output.state
+                                    }));
+                            match *request.mode {
+                                // Shut down without touching `state` which would acknowledge
+                                // latched faults.
+                                Action::Off => output.inhibit = true,
+                                Action::Hold if on => *output.state = State::Hold,
+                                Action::Setpoint if on => *output.pid.setpoint = *request.setpoint,
+                                _ => {}
+                            }
+                            *action = Some(AppliedAction {
+                                action: *request,
+                                previous,
+                                applied: (*output.state, *output.pid.setpoint),
+                            });
+                            changed = true;
+                        }
+                        None => {
+                            let Some(a) = action.take() else {
+                                continue;
+                            };
+                            if *a.action.revert {
+                                output.inhibit = false;
+                                if (*output.state, *output.pid.setpoint) == a.applied {
+                                    log::info!("Reverting alarm action on output {:?}", ch);
+                                    (*output.state, *output.pid.setpoint) = a.previous;
+                                }
+                            }
+                            changed = true;
+                        }
+                        _ => continue,
+                    }
+                    if let Err(e) = output.build_pid() {
+                        log::warn!("Output {:?} settings invalid: {}", ch, e);
+                    }
+                }
+                changed
+            });
+            if changed {
+                settings::spawn().ok();
+            }
+            flash = !flash;
+            c.shared.gpio.lock(|gpio| {
+                for (ch, action) in OutputChannelIdx::iter().zip(actions.iter()) {
+                    if action.is_some_and(|a| *a.action.mode == Action::Flash) {
+                        gpio.set_led(ch.into(), flash.into());
+                    }
+                }
+            });
+
             // Note that you have to wait for a full period of the previous setting first for a change of period to take affect.
             Systick::delay(((*alarm.period * 1000.0) as u32).millis()).await;
         }
     }
 
     // Higher priority than telemetry but lower than adc data readout.
-    #[task(priority = 2, shared=[temperature, statistics, telemetry, settings], local=[iir_state, generator, dac])]
+    #[task(priority = 2, shared=[temperature, statistics, alarm_statistics, telemetry, settings, gpio, fault], local=[iir_state, rate, slew, runaway, settle, excitation, generator, dac, settled, response])]
     async fn process(mut c: process::Context, mut data: Receiver<'static, Data, 4>) {
         while let Ok(Data { phy, ch, adc_code }) = data.recv().await {
+            watchdog::beat(watchdog::Task::Process);
             let temp = c.shared.settings.lock(|settings| {
                 let input = settings.thermostat_eem.input[phy as usize][ch]
                     .as_ref()
@@ -387,59 +1221,286 @@ mod app {
             (
                 &mut c.shared.temperature,
                 &mut c.shared.statistics,
+                &mut c.shared.alarm_statistics,
                 &mut c.shared.telemetry,
                 &mut c.shared.settings,
+                &mut c.shared.gpio,
+                &mut c.shared.fault,
             )
-                .lock(|temperature, statistics, telemetry, settings| {
-                    temperature[phy as usize][ch] = temp;
-                    statistics[phy as usize][ch].update(temp as _);
-
-                    // Start processing when the last ADC has been read out.
-                    // This implies a zero-order hold (aka the input sample will not be updated at every signal processing step) if more than one channel is enabled on an ADC.
-                    if phy != AdcPhy::Three {
-                        return;
-                    }
+                .lock(
+                    |temperature,
+                     statistics,
+                     alarm_statistics,
+                     telemetry,
+                     settings,
+                     gpio,
+                     fault| {
+                        temperature[phy as usize][ch] = temp;
+                        let rate =
+                            c.local.rate[phy as usize][ch].update(temp, Systick::now().ticks());
+                        for buffer in [
+                            &mut statistics[phy as usize][ch],
+                            &mut alarm_statistics[phy as usize][ch],
+                        ] {
+                            buffer.update(temp as _);
+                            buffer.set_rate(rate);
+                        }
 
-                    for ch in OutputChannelIdx::iter() {
-                        let idx = ch as usize;
-                        let current = settings.thermostat_eem.output[idx]
-                            .update(temperature, &mut c.local.iir_state[idx])
-                            as f32;
-                        telemetry.output_current[idx] = current;
-                        c.local.dac.set(ch, DacCode::try_from(current).unwrap());
-                    }
-                    let mut s = Stream {
-                        temperature: [[0.0; 4]; 4],
-                        current: telemetry.output_current,
-                    };
-                    for (t, u) in s
-                        .temperature
-                        .as_flattened_mut()
-                        .iter_mut()
-                        .zip(temperature.as_flattened().iter())
-                    {
-                        *t = *u as _;
-                    }
-                    let b = bytemuck::bytes_of(&s);
-                    c.local.generator.add(|buf| {
-                        for (b, s) in buf.iter_mut().zip(b.iter()) {
-                            b.write(*s);
+                        // Start processing when the last ADC has been read out.
+                        // This implies a zero-order hold (aka the input sample will not be updated at every signal processing step) if more than one channel is enabled on an ADC.
+                        if phy != AdcPhy::Three {
+                            return;
                         }
-                        b.len()
-                    });
-                });
+
+                        let enabled = settings
+                            .thermostat_eem
+                            .input
+                            .each_ref()
+                            .map(|adc| adc.each_ref().map(Option::is_some));
+                        for ch in OutputChannelIdx::iter() {
+                            let idx = ch as usize;
+                            let output = &mut settings.thermostat_eem.output[idx];
+                            let input = output.input(temperature);
+                            let excitation = &mut c.local.excitation[idx];
+                            let (setpoint, offset) = output.excitation.inject(excitation.update(
+                                &output.excitation,
+                                output.pid.period,
+                                *output.state == State::On && !gpio.shutdown(ch),
+                            ));
+                            // Setpoint excitation is equivalent to an input offset.
+                            let x = input - setpoint as f64;
+                            let [min, max] = output.current_range();
+                            let current =
+                                output.update(x, &mut c.local.iir_state[idx]) as f32 + offset;
+                            if current.is_nan() {
+                                // Don't let NaN persist in the filter state.
+                                c.local.iir_state[idx] = Default::default();
+                                telemetry.dac_errors[idx] =
+                                    telemetry.dac_errors[idx].saturating_add(1);
+                                trip(fault, gpio, ch, output, Fault::Nan);
+                            }
+                            let current = current.max(min).min(max);
+                            let current = if *output.state == State::Calibrate {
+                                output.calibration.drive
+                            } else {
+                                current
+                            };
+                            if let Some(r) = excitation.demodulate(input, current) {
+                                if c.local.response.try_send((ch, r)).is_err() {
+                                    log::warn!("Response queue overflow");
+                                }
+                            }
+                            if fault[idx].is_none_or(|f| !output.fault_shutdown(f))
+                                && output.runaway(&mut c.local.runaway[idx], x, current)
+                            {
+                                trip(fault, gpio, ch, output, Fault::Runaway);
+                            }
+                            if fault[idx] != Some(Fault::Interlock)
+                                && output.interlock(temperature, &enabled)
+                            {
+                                trip(fault, gpio, ch, output, Fault::Interlock);
+                            }
+                            let shutdown = gpio.shutdown(ch);
+                            let settled = output.settled(&mut c.local.settle[idx], x, shutdown);
+                            if settled != telemetry.settled[idx] {
+                                telemetry.settled[idx] = settled;
+                                if let Err(e) = c.local.settled.try_send((ch, settled)) {
+                                    log::warn!("Settled queue overflow: {e:?}");
+                                }
+                            }
+                            // The calibration routine steps the current into the reference
+                            // load without the slew rate limit and soft start ramp.
+                            let current = if *output.state == State::Calibrate && !shutdown {
+                                current
+                            } else {
+                                output.slew(&mut c.local.slew[idx], current, shutdown)
+                            };
+                            telemetry.output_current[idx] = current;
+                            let code = match DacCode::calibrated(current, &output.calibration.dac) {
+                                Ok(code) => code,
+                                Err(e) => {
+                                    telemetry.dac_errors[idx] =
+                                        telemetry.dac_errors[idx].saturating_add(1);
+                                    match e {
+                                        DacError::Bounds(code) => code,
+                                        DacError::Nan => {
+                                            trip(fault, gpio, ch, output, Fault::Nan);
+                                            DacCode::ZERO
+                                        }
+                                    }
+                                }
+                            };
+                            c.local.dac.set(ch, code);
+                        }
+                        let mut s = Stream {
+                            temperature: [[0.0; 4]; 4],
+                            current: telemetry.output_current,
+                        };
+                        for (t, u) in s
+                            .temperature
+                            .as_flattened_mut()
+                            .iter_mut()
+                            .zip(temperature.as_flattened().iter())
+                        {
+                            *t = *u as _;
+                        }
+                        let b = bytemuck::bytes_of(&s);
+                        c.local.generator.add(|buf| {
+                            for (b, s) in buf.iter_mut().zip(b.iter()) {
+                                b.write(*s);
+                            }
+                            b.len()
+                        });
+                    },
+                );
+        }
+    }
+
+    #[task(priority = 1, shared=[network])]
+    async fn publish_settled(
+        mut c: publish_settled::Context,
+        mut queue: Receiver<'static, (OutputChannelIdx, bool), 4>,
+    ) {
+        while let Ok((ch, settled)) = queue.recv().await {
+            c.shared
+                .network
+                .lock(|network| network.telemetry.set_settled(ch, settled));
         }
     }
 
-    #[task(priority = 3, binds = EXTI15_10, local=[adc_sm, process])]
+    #[task(priority = 1, shared=[network])]
+    async fn publish_response(
+        mut c: publish_response::Context,
+        mut queue: Receiver<'static, (OutputChannelIdx, Response), 2>,
+    ) {
+        while let Ok((ch, response)) = queue.recv().await {
+            c.shared
+                .network
+                .lock(|network| network.telemetry.publish_response(ch, &response));
+        }
+    }
+
+    #[task(priority = 2, binds = TIM2, shared=[fan])]
+    fn tacho(mut c: tacho::Context) {
+        c.shared.fan.lock(|fan| fan.capture());
+    }
+
+    #[task(priority = 3, binds = EXTI15_10, local=[adc_sm, process, adc_error: [[bool; 4]; 4] = [[false; 4]; 4], overflow: bool = false])]
     fn adc_readout(c: adc_readout::Context) {
-        let (phy, ch, adc_code) = c.local.adc_sm.handle_interrupt();
+        let (phy, ch, adc_code, error) = c.local.adc_sm.handle_interrupt();
+        // Log errors once when they appear.
+        let latched = &mut c.local.adc_error[phy as usize][ch];
+        if error && !*latched {
+            events::record(Event::Adc {
+                adc: phy as u8,
+                channel: ch as u8,
+            });
+        }
+        *latched = error;
         if let Err(e) = c.local.process.try_send(Data { phy, ch, adc_code }) {
             log::warn!("Processing queue overflow: {e:?}");
+            if !*c.local.overflow {
+                *c.local.overflow = true;
+                events::record(Event::Overflow);
+            }
         }
     }
 
-    #[task(priority = 1, shared=[usb, settings], local=[usb_terminal])]
+    /// Output current calibration routine.
+    ///
+    /// Steps the output through the calibration currents, fits the DAC and current sense
+    /// path calibrations, applies them and has them stored in flash.
+    /// Changing the output `state` aborts the routine and keeps the previous calibration.
+    #[task(priority = 1, shared=[settings, adc_internal], local=[calibrated])]
+    async fn calibrate(mut c: calibrate::Context, ch: OutputChannelIdx) {
+        // Number of calibration currents
+        const POINTS: usize = 5;
+        // Number of averaged measurements per current
+        const AVERAGE: usize = 16;
+        let idx = ch as usize;
+
+        // Drive and measure uncalibrated.
+        let (currents, load, previous) = c.shared.settings.lock(|settings| {
+            let output = &mut settings.thermostat_eem.output[idx];
+            let previous = (output.calibration.dac, output.calibration.sense);
+            output.calibration.dac = Default::default();
+            output.calibration.sense = Default::default();
+            (
+                output.calibration_currents::<POINTS>(),
+                *output.calibration.load,
+                previous,
+            )
+        });
+        log::info!(
+            "Output {:?} calibration into {} Ohm: {:?} A",
+            ch,
+            load,
+            currents
+        );
+
+        let mut voltage = [0.0; POINTS];
+        let mut measured = [0.0; POINTS];
+        let mut aborted = false;
+        for (i, current) in currents.iter().enumerate() {
+            aborted = c.shared.settings.lock(|settings| {
+                let output = &mut settings.thermostat_eem.output[idx];
+                output.calibration.drive = *current;
+                *output.state != State::Calibrate
+            });
+            if aborted {
+                break;
+            }
+            // Wait for the TEC driver to settle.
+            Systick::delay(2.secs()).await;
+            for _ in 0..AVERAGE {
+                c.shared.adc_internal.lock(|adc| {
+                    voltage[i] += adc.read_output_voltage(ch) / AVERAGE as f32;
+                    measured[i] += adc.read_output_current(ch, &Affine::default()) / AVERAGE as f32;
+                });
+                Systick::delay(10.millis()).await;
+            }
+        }
+
+        // Delivered current from the voltage across the load.
+        // The voltage sense polarity is taken from the data.
+        let polarity = if voltage[POINTS - 1] < voltage[0] {
+            -1.0
+        } else {
+            1.0
+        };
+        let delivered = voltage.map(|v| polarity * v / load);
+        let fit = (!aborted)
+            .then(|| Affine::fit(&currents, &delivered).zip(Affine::fit(&delivered, &measured)))
+            .flatten();
+
+        c.shared.settings.lock(|settings| {
+            let output = &mut settings.thermostat_eem.output[idx];
+            output.calibration.drive = 0.0;
+            (output.calibration.dac, output.calibration.sense) = fit.unwrap_or(previous);
+            if !aborted {
+                *output.state = State::Off;
+            }
+        });
+        if let Some((dac, sense)) = fit {
+            log::info!(
+                "Output {:?} calibration: DAC {:?}, sense {:?}",
+                ch,
+                dac,
+                sense
+            );
+            if c.local.calibrated.try_send(ch).is_err() {
+                log::warn!("Calibration store queue overflow");
+            }
+        } else if !aborted {
+            log::warn!("Output {:?} calibration failed", ch);
+        }
+        if !aborted {
+            // Apply the state change. This also starts pending calibrations of other channels.
+            settings::spawn().ok();
+        }
+    }
+
+    #[task(priority = 1, shared=[usb, settings, network], local=[usb_terminal, calibration_store])]
     async fn usb(mut c: usb::Context) {
         loop {
             // Handle the USB serial terminal.
@@ -449,10 +1510,99 @@ mod app {
 
             c.shared.settings.lock(|settings| {
                 if c.local.usb_terminal.poll(settings).unwrap() {
-                    settings::spawn().unwrap()
+                    events::record(Event::Settings);
+                    // A pending run (spawned by another task) applies the change as well.
+                    settings::spawn().ok();
+                }
+                // Persist new calibrations.
+                while let Ok(ch) = c.local.calibration_store.try_recv() {
+                    for node in ["dac/offset", "dac/gain", "sense/offset", "sense/gain"] {
+                        let mut path: String<128> = String::new();
+                        write!(
+                            &mut path,
+                            "/thermostat_eem/output/{}/calibration/{node}",
+                            ch as usize
+                        )
+                        .unwrap();
+                        c.local.usb_terminal.platform_mut().save(settings, &path);
+                    }
                 }
             });
 
+            // Persist new events and serve event log requests.
+            // A single record per poll bounds the time the blocking flash write holds up the
+            // other priority 1 tasks.
+            let storage = &mut c.local.usb_terminal.platform_mut().storage;
+            if let Some(record) = events::pop() {
+                events::append(storage, &record);
+            }
+            match c
+                .shared
+                .network
+                .lock(|network| network.telemetry.take_events_request())
+            {
+                Some(Request::Read) => {
+                    // Keep the most recent records.
+                    let mut records: heapless::Deque<Record, 32> = heapless::Deque::new();
+                    events::read(storage, |record| {
+                        if records.is_full() {
+                            records.pop_front();
+                        }
+                        records.push_back(record).ok();
+                    });
+                    let records: heapless::Vec<Record, 32> = records.into_iter().collect();
+                    c.shared
+                        .network
+                        .lock(|network| network.telemetry.publish_events(&records));
+                }
+                Some(Request::Clear) => {
+                    log::info!("Clearing event log");
+                    events::clear(storage);
+                }
+                None => {}
+            }
+
+            Systick::delay(10.millis()).await;
+        }
+    }
+
+    /// Feed the watchdog while all supervised tasks make progress.
+    ///
+    /// Runs above the supervised tasks to keep feeding during long blocking operations
+    /// (flash erase) at lower priority.
+    #[task(priority = 3, local=[supervisor])]
+    async fn supervisor(c: supervisor::Context) {
+        let mut reported = false;
+        loop {
+            if let Some(task) = c.local.supervisor.update(Systick::now().ticks()) {
+                if !reported {
+                    log::error!("Task {:?} stalled, awaiting watchdog reset", task);
+                    reported = true;
+                }
+            }
+            Systick::delay(100.millis()).await;
+        }
+    }
+
+    /// Overtemperature interlock.
+    ///
+    /// Polls the hardware overtemperature signal and shuts all outputs down on assertion.
+    #[task(priority = 2, shared=[gpio, interlock])]
+    async fn overtemp(mut c: overtemp::Context) {
+        loop {
+            (&mut c.shared.gpio, &mut c.shared.interlock).lock(|gpio, interlock| {
+                if gpio.overtemp() {
+                    for ch in OutputChannelIdx::iter() {
+                        gpio.set_shutdown(ch, true.into());
+                        gpio.set_led(ch.into(), false.into());
+                    }
+                    let uptime = Systick::now().duration_since_epoch().to_secs();
+                    if interlock.trip(uptime) {
+                        log::error!("Overtemperature interlock tripped");
+                        events::record(Event::Overtemp);
+                    }
+                }
+            });
             Systick::delay(10.millis()).await;
         }
     }
@@ -14,6 +14,7 @@ pub mod network_processor;
 pub mod telemetry;
 
 use crate::{
+    alarm::{Limit, SwitchedLimit},
     hardware::{metadata::ApplicationMetadata, EthernetPhy, NetworkManager, NetworkStack},
     settings::NetSettings,
     SystemTimer,
@@ -29,14 +30,17 @@ use miniconf::{Leaf, Tree, TreeDeserializeOwned, TreeKey, TreeSerialize};
 pub type NetworkReference = smoltcp_nal::shared::NetworkStackProxy<'static, NetworkStack>;
 
 pub struct MqttStorage {
-    telemetry: [u8; 2048],
+    /// The largest telemetry client message is the telemetry with all 16 inputs enabled:
+    /// about 3 kB of JSON with worst case number formatting. Structured alarm payloads and
+    /// event log responses are of similar size.
+    telemetry: [u8; 4096],
     settings: [u8; 1024],
 }
 
 impl Default for MqttStorage {
     fn default() -> Self {
         Self {
-            telemetry: [0u8; 2048],
+            telemetry: [0u8; 4096],
             settings: [0u8; 1024],
         }
     }
@@ -136,8 +140,8 @@ where
                 stack_manager.acquire_stack(),
                 clock,
                 minimq::ConfigBuilder::new(named_broker, &mut store.telemetry)
-                    // The telemetry client doesn't receive any messages except MQTT control packets.
-                    // As such, we don't need much of the buffer for RX.
+                    // The telemetry client only receives MQTT control packets and short event log
+                    // requests. As such, we don't need much of the buffer for RX.
                     .rx_buffer(minimq::config::BufferConfig::Maximum(100))
                     .session_state(minimq::config::BufferConfig::Maximum(0))
                     .client_id(&get_client_id(&net_settings.id, "tlm"))
@@ -235,15 +239,124 @@ pub fn get_device_prefix(app: &str, id: &str) -> String<128> {
     prefix
 }
 
+/// Value of an input channel that is checked against its alarm limit.
+#[derive(Copy, Clone, Default, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Evaluation {
+    /// The most recent temperature.
+    #[default]
+    Sample,
+    /// The mean temperature over the last alarm period.
+    Mean,
+    /// The lowest and highest temperature over the last alarm period.
+    Extreme,
+}
+
+/// Alarm payload format.
+#[derive(Copy, Clone, Default, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Payload {
+    /// `true` or `false`
+    #[default]
+    Bool,
+    /// JSON object with the alarm state, the highest severity and the asserted limits with
+    /// their input channel, value, violated limit, violation duration and severity.
+    Json,
+}
+
+/// Device quantity monitored by an [AlarmSource].
+///
+/// The [Monitor](crate::Monitor) measurements are updated with the telemetry period.
+#[derive(Copy, Clone, Default, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Source {
+    /// The source is disabled.
+    #[default]
+    None,
+    /// 3.3 V rail voltage in Volt
+    P3v3Voltage,
+    /// 5 V rail voltage in Volt
+    P5vVoltage,
+    /// 12 V rail voltage in Volt
+    P12vVoltage,
+    /// 12 V supply current in Ampere
+    P12vCurrent,
+    /// Measured TEC current of the output `index` in Ampere
+    OutputCurrent,
+    /// Measured TEC voltage of the output `index` in Volt
+    OutputVoltage,
+    /// Hardware overtemperature signal. One if asserted, zero otherwise.
+    Overtemp,
+    /// Latched fault of the output `index`. One if present, zero otherwise.
+    Fault,
+    /// Number of ADC conversion errors since the last alarm period.
+    AdcErrors,
+    /// Number of network link losses since the last alarm period.
+    /// Alarms are only delivered with the link up. Use `latch` to retain them.
+    LinkLosses,
+}
+
+/// Alarm on a device quantity.
+///
+/// The source contributes to the alarm on the alarm `target`. Its own state is additionally
+/// published onto its `target`.
+#[derive(Clone, Debug, Tree)]
+pub struct AlarmSource {
+    /// Monitored quantity.
+    ///
+    /// # Value
+    /// See [Source]
+    pub source: Leaf<Source>,
+
+    /// Output channel index for the per-output quantities.
+    ///
+    /// # Value
+    /// `0..=3`
+    #[tree(validate=self.validate_index)]
+    pub index: Leaf<u8>,
+
+    /// Limits on the quantity. Set the limits before enabling the source.
+    ///
+    /// # Value
+    /// See [Limit]
+    pub limit: Limit,
+
+    /// Additional MQTT target for the state of this source.
+    ///
+    /// # Value
+    /// Full path to the desired target. No wildcards. Empty to only publish on the alarm
+    /// `target`.
+    pub target: Leaf<String<128>>,
+}
+
+impl Default for AlarmSource {
+    fn default() -> Self {
+        Self {
+            source: Default::default(),
+            index: 0.into(),
+            limit: Default::default(),
+            target: Default::default(),
+        }
+    }
+}
+
+impl AlarmSource {
+    fn validate_index(&mut self, depth: usize) -> Result<usize, &'static str> {
+        if *self.index > 3 {
+            return Err("Output index out of range");
+        }
+        Ok(depth)
+    }
+}
+
 /// Miniconf settings for the MQTT alarm.
 /// The alarm simply publishes "false" onto its `target` as long as all the channels are
 /// within their `temperature_limits`` (aka logical OR of all channels).
 /// Otherwise it publishes "true" (aka true, there is an alarm).
+/// Alternatively the alarm cause details are published, see [Payload].
 ///
-/// The publishing interval is given by `period_ms`.
+/// The publishing interval is given by `period`.
 ///
-/// The alarm is non-latching. If alarm was "true" for a while and the temperatures come within
-/// limits again, alarm will be "false" again.
+/// By default the alarm is non-latching. If alarm was "true" for a while and the temperatures
+/// come within limits again, alarm will be "false" again. With `latch` the alarm stays "true"
+/// until it is acknowledged.
 #[derive(Clone, Debug, Tree)]
 pub struct Alarm {
     /// Set the alarm to armed (true) or disarmed (false).
@@ -261,18 +374,47 @@ pub struct Alarm {
     /// Any string up to 128 characters.
     pub target: Leaf<String<128>>,
 
-    /// Alarm period in milliseconds.
+    /// Alarm payload format.
+    ///
+    /// # Value
+    /// See [Payload]
+    pub payload: Leaf<Payload>,
+
+    /// Alarm period in seconds.
     /// The alarm will publish its state with this period.
     ///
     /// # Value
     /// f32
     pub period: Leaf<f32>,
 
+    /// Keep the alarm asserted after the temperatures are within their limits again until it is
+    /// acknowledged.
+    ///
+    /// # Value
+    /// `true` or `false`
+    pub latch: Leaf<bool>,
+
+    /// Acknowledge a latched alarm.
+    ///
+    /// Setting `true` clears the alarm. It asserts again if a violation persists.
+    /// The value is reset to `false` once processed.
+    ///
+    /// # Value
+    /// `true` or `false`
+    pub acknowledge: Leaf<bool>,
+
+    /// Value of the input channels that is checked against the limits.
+    ///
+    /// # Value
+    /// See [Evaluation]
+    pub evaluation: Leaf<Evaluation>,
+
     /// Temperature limits for the alarm.
     ///
-    /// Array of lower and upper limits for the valid temperature range of the alarm.
+    /// Lower and upper limits for the valid temperature range of the alarm with hysteresis and
+    /// a minimum violation duration, see [Limit].
     /// The alarm will be asserted if any of the enabled input channels goes below its minimum or above its maximum temperature.
-    /// The alarm is non latching and clears itself once all channels are in their respective limits.
+    /// Unless latching, the alarm clears itself once all channels are in their respective limits.
     ///
     /// # Path
     /// `temperature_limits/<adc>/<channel>`
@@ -280,8 +422,32 @@ pub struct Alarm {
     /// * `<channel>` specifies which channel of an ADC to configure. Only the enabled channels for the specific ADC are available.
     ///
     /// # Value
-    /// `[f32, f32]` or `None`
-    pub temperature_limits: [[Option<[Leaf<f32>; 2]>; 4]; 4],
+    /// See [Limit] or `None`
+    pub temperature_limits: [[Option<Limit>; 4]; 4],
+
+    /// Rate of change limits for the alarm.
+    ///
+    /// Lower and upper limits of the filtered rate of change of the input temperatures in K/s,
+    /// see [Limit]. They are checked against the most recent rate.
+    ///
+    /// # Path
+    /// `rate_limits/<adc>/<channel>`
+    /// * `<adc> := [0, 1, 2, 3]` specifies which adc to configure.
+    /// * `<channel> := [0, 1, 2, 3]` specifies which channel of an ADC to configure.
+    ///
+    /// # Value
+    /// See [SwitchedLimit]
+    pub rate_limits: [[SwitchedLimit; 4]; 4],
+
+    /// Alarms on supply, output and device health quantities.
+    ///
+    /// # Path
+    /// `sources/<n>`
+    /// * `<n> := [0, 7]`
+    ///
+    /// # Value
+    /// See [AlarmSource]
+    pub sources: [AlarmSource; 8],
 }
 
 impl Default for Alarm {
@@ -289,8 +455,14 @@ impl Default for Alarm {
         Self {
             armed: false.into(),
             target: Default::default(),
+            payload: Default::default(),
             period: 1.0.into(),
+            latch: false.into(),
+            acknowledge: false.into(),
+            evaluation: Default::default(),
             temperature_limits: Default::default(),
+            rate_limits: Default::default(),
+            sources: Default::default(),
         }
     }
 }
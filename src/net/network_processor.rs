@@ -11,6 +11,7 @@ pub struct NetworkProcessor {
     stack: NetworkReference,
     phy: EthernetPhy,
     network_was_reset: bool,
+    link_losses: u32,
 }
 
 impl NetworkProcessor {
@@ -28,6 +29,7 @@ impl NetworkProcessor {
             stack,
             phy,
             network_was_reset: false,
+            link_losses: 0,
         }
     }
 
@@ -50,12 +52,18 @@ impl NetworkProcessor {
             (false, false) => {
                 log::warn!("Network link DOWN");
                 self.network_was_reset = true;
+                self.link_losses = self.link_losses.wrapping_add(1);
                 self.stack.lock(|stack| stack.handle_link_reset());
             }
             _ => {}
         };
     }
 
+    /// Number of network link losses since boot.
+    pub fn link_losses(&self) -> u32 {
+        self.link_losses
+    }
+
     /// Process and update the state of the network.
     ///
     /// # Note
@@ -10,12 +10,17 @@
 //! sampling frequency. Instead, the raw codes are stored and the telemetry is generated as
 //! required immediately before transmission. This ensures that any slower computation required
 //! for unit conversion can be off-loaded to lower priority tasks.
+use core::fmt::Write;
 use heapless::String;
 use minimq::{DeferredPublication, Publication};
 use serde::Serialize;
 
 use super::NetworkReference;
-use crate::hardware::{metadata::ApplicationMetadata, SystemTimer};
+use crate::events::{Record, Request};
+use crate::excitation::Response;
+use crate::hardware::{
+    gpio::TecFrequency, metadata::ApplicationMetadata, OutputChannelIdx, SystemTimer,
+};
 
 /// Default metadata message if formatting errors occur.
 const DEFAULT_METADATA: &str = "{\"message\":\"Truncated: See USB terminal\"}";
@@ -30,7 +35,11 @@ pub struct TelemetryClient {
     >,
     prefix: &'static str,
     meta_published: bool,
-    metadata: &'static ApplicationMetadata,
+    metadata: ApplicationMetadata,
+    settled: [bool; 4],
+    settled_pending: [bool; 4],
+    subscribed: bool,
+    events_request: Option<Request>,
 }
 
 impl TelemetryClient {
@@ -56,7 +65,21 @@ impl TelemetryClient {
             mqtt,
             meta_published: false,
             prefix,
-            metadata,
+            metadata: *metadata,
+            settled: [false; 4],
+            settled_pending: [true; 4],
+            subscribed: false,
+            events_request: None,
+        }
+    }
+
+    /// Update the TEC driver switching frequency reported in the metadata.
+    ///
+    /// The metadata is published again if the frequency changed.
+    pub fn set_tec_frequency(&mut self, frequency: TecFrequency) {
+        if self.metadata.tec_frequency != frequency {
+            self.metadata.tec_frequency = frequency;
+            self.meta_published = false;
         }
     }
 
@@ -86,7 +109,7 @@ impl TelemetryClient {
 
     /// A secondary functionality tugged onto the telemetry client that publishes onto another
     /// `alarm_topic`.
-    pub fn publish_alarm(&mut self, alarm_topic: &String<128>, alarm: &bool) {
+    pub fn publish_alarm<T: Serialize>(&mut self, alarm_topic: &String<128>, alarm: &T) {
         self.mqtt
             .client()
             .publish(
@@ -99,6 +122,100 @@ impl TelemetryClient {
             .ok();
     }
 
+    /// Publish a transfer function measurement result onto `<prefix>/response/<n>`.
+    ///
+    /// # Note
+    /// The result is published in a "best-effort" fashion like the telemetry.
+    ///
+    /// # Args
+    /// * `ch` - The output channel
+    /// * `response` - The measurement result
+    pub fn publish_response(&mut self, ch: OutputChannelIdx, response: &Response) {
+        let mut topic: String<128> = String::new();
+        write!(&mut topic, "{}/response/{}", self.prefix, ch as usize).unwrap();
+
+        self.mqtt
+            .client()
+            .publish(
+                DeferredPublication::new(|buf| serde_json_core::to_slice(response, buf))
+                    .topic(&topic)
+                    .finish()
+                    .unwrap(),
+            )
+            .map_err(|e| log::error!("Response publishing error: {:?}", e))
+            .ok();
+    }
+
+    /// Take a pending event log request received on `<prefix>/events/request`.
+    ///
+    /// # Note
+    /// Any payload requests the event log. The payload `clear` requests clearing the log.
+    pub fn take_events_request(&mut self) -> Option<Request> {
+        self.events_request.take()
+    }
+
+    /// Publish event log records onto `<prefix>/events/response`.
+    ///
+    /// # Args
+    /// * `records` - The records, oldest first
+    pub fn publish_events(&mut self, records: &[Record]) {
+        let mut topic: String<128> = self.prefix.try_into().unwrap();
+        topic.push_str("/events/response").unwrap();
+
+        self.mqtt
+            .client()
+            .publish(
+                DeferredPublication::new(|buf| serde_json_core::to_slice(records, buf))
+                    .topic(&topic)
+                    .finish()
+                    .unwrap(),
+            )
+            .map_err(|e| log::error!("Event log publishing error: {:?}", e))
+            .ok();
+    }
+
+    /// Update the settled state of an output channel.
+    ///
+    /// # Note
+    /// The state is published as a retained message onto `<prefix>/settled/<n>` during the
+    /// next [TelemetryClient::update] and republished after a reconnection to the broker.
+    ///
+    /// # Args
+    /// * `ch` - The output channel
+    /// * `settled` - The settled state
+    pub fn set_settled(&mut self, ch: OutputChannelIdx, settled: bool) {
+        self.settled[ch as usize] = settled;
+        self.settled_pending[ch as usize] = true;
+    }
+
+    /// Publish pending settled states as retained messages.
+    fn publish_settled(&mut self) {
+        for (idx, (settled, pending)) in self
+            .settled
+            .iter()
+            .zip(self.settled_pending.iter_mut())
+            .enumerate()
+        {
+            if !*pending || !self.mqtt.client().can_publish(minimq::QoS::AtMostOnce) {
+                continue;
+            }
+            let mut topic: String<128> = String::new();
+            write!(&mut topic, "{}/settled/{idx}", self.prefix).unwrap();
+            *pending = self
+                .mqtt
+                .client()
+                .publish(
+                    DeferredPublication::new(|buf| serde_json_core::to_slice(settled, buf))
+                        .topic(&topic)
+                        .retain()
+                        .finish()
+                        .unwrap(),
+                )
+                .map_err(|e| log::error!("Settled publishing error: {:?}", e))
+                .is_err();
+        }
+    }
+
     /// Update the telemetry client
     ///
     /// # Note
@@ -106,7 +223,20 @@ impl TelemetryClient {
     /// and outgoing messages. Without this, the client will never connect to the broker. This
     /// should be called regularly.
     pub fn update(&mut self) {
-        match self.mqtt.poll(|_client, _topic, _message, _properties| {}) {
+        let Self {
+            ref mut mqtt,
+            ref mut events_request,
+            ..
+        } = self;
+        match mqtt.poll(|_client, topic, message, _properties| {
+            if topic.ends_with("/events/request") {
+                *events_request = Some(if message == b"clear" {
+                    Request::Clear
+                } else {
+                    Request::Read
+                });
+            }
+        }) {
             Err(minimq::Error::Network(smoltcp_nal::NetworkError::TcpConnectionFailure(
                 smoltcp_nal::smoltcp::socket::tcp::ConnectError::Unaddressable,
             ))) => {}
@@ -117,9 +247,24 @@ impl TelemetryClient {
 
         if !self.mqtt.client().is_connected() {
             self.meta_published = false;
+            self.settled_pending = [true; 4];
+            self.subscribed = false;
             return;
         }
 
+        if !self.subscribed {
+            let mut topic: String<128> = self.prefix.try_into().unwrap();
+            topic.push_str("/events/request").unwrap();
+            self.subscribed = self
+                .mqtt
+                .client()
+                .subscribe(&[minimq::types::TopicFilter::new(&topic)], &[])
+                .map_err(|e| log::error!("Event log subscription error: {:?}", e))
+                .is_ok();
+        }
+
+        self.publish_settled();
+
         // Publish application metadata
         if !self.meta_published && self.mqtt.client().can_publish(minimq::QoS::AtMostOnce) {
             let Self {
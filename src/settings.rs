@@ -22,7 +22,10 @@
 //!    settings values
 //! 3. Unknown/unneeded settings values in flash can be actively ignored, facilitating simple flash
 //!    storage sharing.
-use crate::hardware::{flash::Flash, metadata::ApplicationMetadata, platform};
+use crate::{
+    events,
+    hardware::{flash::Flash, metadata::ApplicationMetadata, platform},
+};
 use core::fmt::Write;
 use embassy_futures::block_on;
 use embedded_io::Write as EioWrite;
@@ -111,20 +114,101 @@ impl<C, const Y: usize> SerialSettingsPlatform<C, Y>
 where
     C: TreeDeserializeOwned + TreeSerialize + TreeKey,
 {
+    /// Load the settings from flash.
+    ///
+    /// Earlier firmware used the entire flash bank for settings storage. If the settings map
+    /// extends into the event log range (see [Flash::event_range]) it is migrated into the
+    /// settings range once.
     pub fn load(structure: &mut C, storage: &mut Flash) {
+        let range = storage.range();
+        if events::valid(storage) {
+            match Self::load_range(structure, storage, range.clone(), |_| {}) {
+                Err(sequential_storage::Error::Corrupted { .. }) => {}
+                _ => return,
+            }
+        }
+
+        log::warn!("Migrating settings from an earlier flash layout");
+        // Bit set of the node indices loaded from flash.
+        let mut loaded = [0u32; 128];
+        let legacy = storage.legacy_range();
+        if let Err(e) = Self::load_range(structure, storage, legacy.clone(), |index| {
+            match loaded.get_mut(index / 32) {
+                Some(word) => *word |= 1 << (index % 32),
+                None => log::warn!("Setting {index} not migrated"),
+            }
+        }) {
+            log::error!("Failed to load settings: {e:?}");
+        }
+        if let Err(e) = block_on(sequential_storage::erase_all(storage, legacy)) {
+            log::error!("Failed to erase settings: {e:?}");
+            return;
+        }
+
+        let mut value = [0u8; 128];
+        let mut buffer = [0u8; 512];
+        for (index, path) in C::nodes::<Path<String<128>, '/'>, Y>().enumerate() {
+            let (path, _node) = path.unwrap();
+            if loaded[index / 32] & (1 << (index % 32)) == 0 {
+                continue;
+            }
+            let value: &[u8] = match postcard::get_by_key(
+                structure,
+                &path,
+                ::postcard::ser_flavors::Slice::new(&mut value),
+            ) {
+                Ok(value) => value,
+                Err(e) => {
+                    log::warn!("Failed to serialize `{}`: {e:?}", path.as_str());
+                    continue;
+                }
+            };
+            if let Err(e) = block_on(store_item(
+                storage,
+                range.clone(),
+                &mut NoCache::new(),
+                &mut buffer,
+                &SettingsKey(path.clone().into_inner().into_bytes()),
+                &value,
+            )) {
+                log::warn!("Failed to store `{}` to flash: {e:?}", path.as_str());
+            }
+        }
+    }
+
+    /// Load the settings from a flash range.
+    ///
+    /// # Args
+    /// * `structure` - The settings
+    /// * `storage` - The flash
+    /// * `range` - The flash range of the settings map
+    /// * `loaded` - Called with the index of each node loaded
+    fn load_range(
+        structure: &mut C,
+        storage: &mut Flash,
+        range: core::ops::Range<u32>,
+        mut loaded: impl FnMut(usize),
+    ) -> Result<
+        (),
+        sequential_storage::Error<<Flash as embedded_storage::nor_flash::ErrorType>::Error>,
+    > {
         // Loop over flash and read settings
         let mut buffer = [0u8; 512];
-        for path in C::nodes::<Path<String<128>, '/'>, Y>() {
+        for (index, path) in C::nodes::<Path<String<128>, '/'>, Y>().enumerate() {
             let (path, _node) = path.unwrap();
 
             // Try to fetch the setting from flash.
             let value: &[u8] = match block_on(fetch_item(
                 storage,
-                storage.range(),
+                range.clone(),
                 &mut NoCache::new(),
                 &mut buffer,
                 &SettingsKey(path.clone().into_inner().into_bytes()),
             )) {
+                Err(e @ sequential_storage::Error::Corrupted { .. }) => {
+                    log::error!("Settings in flash corrupted");
+                    return Err(e);
+                }
                 Err(e) => {
                     log::warn!("Failed to fetch `{}` from flash: {e:?}", path.as_str());
                     continue;
@@ -142,13 +226,45 @@ where
             log::info!("Loading initial `{}` from flash", path.as_str());
 
             let flavor = ::postcard::de_flavors::Slice::new(value);
-            if let Err(e) = postcard::set_by_key(structure, &path, flavor) {
-                log::warn!(
+            match postcard::set_by_key(structure, &path, flavor) {
+                Ok(_) => loaded(index),
+                Err(e) => log::warn!(
                     "Failed to deserialize `{}` from flash: {e:?}",
                     path.as_str()
-                );
+                ),
             }
         }
+        Ok(())
+    }
+}
+
+impl<C, const Y: usize> SerialSettingsPlatform<C, Y>
+where
+    C: Settings,
+{
+    /// Store the current value of a setting to flash.
+    ///
+    /// # Args
+    /// * `structure` - The settings
+    /// * `path` - Path of the setting
+    pub fn save(&mut self, structure: &C, path: &str) {
+        let mut value = [0u8; 128];
+        let mut buffer = [0u8; 512];
+        let value = match postcard::get_by_key(
+            structure,
+            &Path::<_, '/'>(path),
+            ::postcard::ser_flavors::Slice::new(&mut value),
+        ) {
+            Ok(value) => value,
+            Err(e) => {
+                log::warn!("Failed to serialize `{path}`: {e:?}");
+                return;
+            }
+        };
+        log::info!("Storing `{path}` to flash");
+        if let Err(e) = self.store(&mut buffer, path.as_bytes(), value) {
+            log::warn!("Failed to store `{path}` to flash: {e:?}");
+        }
     }
 }
 
@@ -229,11 +345,34 @@ where
                     "Panic Info", self.metadata.panic_info
                 )
                 .unwrap();
+                writeln!(
+                    &mut self.interface,
+                    "{:<20}: {}",
+                    "Watchdog Reset", self.metadata.watchdog_reset
+                )
+                .unwrap();
+            }
+            "events" => {
+                let mut count = 0;
+                events::read(&mut self.storage, |record| {
+                    writeln!(
+                        &mut self.interface,
+                        "{:>10} s: {:?}",
+                        record.uptime, record.event
+                    )
+                    .unwrap();
+                    count += 1;
+                });
+                writeln!(&mut self.interface, "{count} events").unwrap();
+            }
+            "events-clear" => {
+                events::clear(&mut self.storage);
+                writeln!(&mut self.interface, "Event log cleared").unwrap();
             }
             _ => {
                 writeln!(
                     self.interface_mut(),
-                    "Invalid platform command: `{cmd}` not in [`dfu`, `reboot`, `service`]"
+                    "Invalid platform command: `{cmd}` not in [`dfu`, `reboot`, `service`, `events`, `events-clear`]"
                 )
                 .ok();
             }
@@ -4,12 +4,14 @@ use num_traits::Float;
 use serde::Serialize;
 
 /// Statistics telemetry struct. Contains the mean, peak-to-peak and standard deviation temperature
-/// over the last telemetry period.
+/// over the last telemetry period and the most recent filtered rate of change.
 #[derive(Serialize, Copy, Clone, Debug)]
 pub struct Statistics {
     mean: f32,
     ptp: f32,
     std: f32,
+    /// Rate of change in K/s. `None` until enough samples have been processed.
+    rate: Option<f32>,
 }
 
 impl From<Buffer> for Option<Statistics> {
@@ -23,6 +25,7 @@ impl From<Buffer> for Option<Statistics> {
                 mean: mean + buff.x0,
                 ptp: buff.max - buff.min,
                 std: var.sqrt(),
+                rate: buff.rate,
             })
         } else {
             None
@@ -39,9 +42,30 @@ pub struct Buffer {
     m2: f32,
     x0: f32,
     counter: u32,
+    rate: Option<f32>,
 }
 
 impl Buffer {
+    /// Set the most recent rate of change.
+    pub fn set_rate(&mut self, rate: Option<f32>) {
+        self.rate = rate;
+    }
+
+    /// Most recent rate of change.
+    pub fn rate(&self) -> Option<f32> {
+        self.rate
+    }
+
+    /// Mean of the buffered samples. `None` if the buffer is empty.
+    pub fn mean(&self) -> Option<f32> {
+        (self.counter > 0).then(|| self.m1 / self.counter as f32 + self.x0)
+    }
+
+    /// Lowest and highest buffered sample. `None` if the buffer is empty.
+    pub fn range(&self) -> Option<[f32; 2]> {
+        (self.counter > 0).then_some([self.min, self.max])
+    }
+
     /// Add a new temperature sample to the buffer. This will add it to the accumulator,
     /// update min/max and increment the counter.
     pub fn update(&mut self, x: f32) {
@@ -66,6 +90,7 @@ impl Default for Buffer {
             x0: f32::NAN,
             max: f32::NEG_INFINITY,
             min: f32::INFINITY,
+            rate: None,
         }
     }
 }